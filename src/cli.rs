@@ -1,4 +1,5 @@
 use clap::{Parser, Subcommand};
+use clap_complete::Shell;
 use std::path::PathBuf;
 
 #[derive(Parser)]
@@ -6,21 +7,157 @@ use std::path::PathBuf;
 pub struct Cli {
     #[command(subcommand)]
     pub command: Option<Commands>,
+    /// also list the directories known to zoxide
+    #[arg(long)]
+    pub zoxide: bool,
+    /// print the picked directory instead of opening a session, e.g.
+    /// cd "$(tmux-fzy --print)"
+    #[arg(long)]
+    pub print: bool,
+    /// stop walking after this many directories, overrides max_nodes
+    #[arg(long, value_name = "N")]
+    pub max_nodes: Option<usize>,
+    /// stop walking after this many seconds, overrides max_time
+    #[arg(long, value_name = "SECS")]
+    pub max_time: Option<u64>,
+    /// name of the tmux socket to use, like tmux -L
+    #[arg(short = 'L', long, global = true, value_name = "NAME")]
+    pub socket_name: Option<String>,
+    /// path of the tmux socket to use, like tmux -S
+    #[arg(short = 'S', long, global = true, value_name = "PATH")]
+    pub socket_path: Option<PathBuf>,
+    /// print the tmux commands that would change something to stderr instead
+    /// of running them
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+    /// append debug logs to this file, same as setting TMUX_FZY_LOG
+    #[arg(long, global = true, value_name = "PATH")]
+    pub log: Option<PathBuf>,
+    /// print plain text, same as setting NO_COLOR
+    #[arg(long, global = true)]
+    pub no_color: bool,
 }
 
 #[derive(Subcommand, Debug)]
 pub enum Commands {
     Add {
-        #[arg(long, default_value_t = 0)]
-        maxdepth: usize,
-        #[arg(long, default_value_t = 0)]
-        mindepth: usize,
+        /// [default: default_max_depth from the config, else 0]
+        #[arg(long)]
+        maxdepth: Option<usize>,
+        /// [default: default_min_depth from the config, else 0]
+        #[arg(long)]
+        mindepth: Option<usize>,
+        /// sets both --mindepth and --maxdepth, passing either of them with a
+        /// different value is an error
+        #[arg(long)]
+        depth: Option<usize>,
+        /// follow symlinked directories while walking
+        #[arg(long)]
+        follow_links: bool,
+        /// walk into hidden directories like .config
+        #[arg(long)]
+        show_hidden: bool,
+        /// command to run in newly created sessions, e.g. "nvim ."
+        #[arg(long)]
+        command: Option<String>,
+        /// environment variable set in newly created sessions, repeatable
+        #[arg(long, value_name = "KEY=VAL", value_parser = parse_env)]
+        env: Vec<(String, String)>,
+        /// number of panes to split newly created sessions into
+        #[arg(long)]
+        panes: Option<usize>,
+        /// tmux layout for the panes, e.g. "main-vertical"
+        #[arg(long)]
+        layout: Option<String>,
+        /// name sessions after this template instead of the directory, e.g.
+        /// "{parent}-{leaf}", "{root}" is the added directory's name
+        #[arg(long, value_name = "TEMPLATE")]
+        session_name: Option<String>,
+        /// window to land in when opening the session, created if missing
+        #[arg(long)]
+        target_window: Option<String>,
+        /// list files as well as directories
+        #[arg(long)]
+        include_files: bool,
+        /// command to open a picked file with, e.g. "nvim", implies
+        /// --include-files
+        #[arg(long)]
+        file_command: Option<String>,
         paths: Vec<PathBuf>,
     },
 
-    List,
+    List {
+        /// print the entries as a json array
+        #[arg(long)]
+        json: bool,
+    },
 
     Del {
+        /// indices as shown by `list`, repeatable or comma separated
+        #[arg(short, long, value_delimiter = ',')]
+        index: Vec<usize>,
         paths: Vec<PathBuf>,
     },
+
+    /// move the entry at index FROM to index TO, as shown by `list`
+    Move { from: usize, to: usize },
+
+    /// remove every configured path
+    Clear {
+        /// don't ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+
+    /// kill every tmux session except the one you are attached to
+    KillAll,
+
+    /// open the paths file in $EDITOR
+    Edit,
+
+    /// print where the paths file and the config are looked for
+    Paths,
+
+    /// print a completion script for the given shell to stdout
+    Completions { shell: Shell },
+}
+
+/// Splits `KEY=VAL` on the first `=`, the value may be empty but the key may not
+fn parse_env(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VAL, got '{}'", s)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    fn completions(shell: Shell) -> String {
+        let mut out = Vec::new();
+        clap_complete::generate(shell, &mut Cli::command(), "tmux-fzy", &mut out);
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn completion_scripts() {
+        Cli::command().debug_assert();
+        let cli = Cli::try_parse_from(["tmux-fzy", "completions", "zsh"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Completions { shell: Shell::Zsh })
+        ));
+
+        let bash = completions(Shell::Bash);
+        assert!(bash.contains("complete -F _tmux__fzy"));
+        let zsh = completions(Shell::Zsh);
+        assert!(zsh.starts_with("#compdef tmux-fzy"));
+        for script in [bash, zsh] {
+            for word in ["add", "completions", "--max-nodes", "--socket-name"] {
+                assert!(script.contains(word), "{} missing", word);
+            }
+        }
+    }
 }
@@ -1,24 +1,143 @@
 use std::{
+    borrow::Cow,
+    collections::BTreeMap,
     env,
-    ffi::OsString,
+    ffi::{OsStr, OsString},
+    fmt::Display,
     fs::{self, File, OpenOptions},
     io::{Read, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
     str::FromStr,
+    time::SystemTime,
 };
 
+use crossterm::style::{Colored, ContentStyle, StyledContent};
 use ratatui::style::Color;
+use serde::{Deserialize, Serialize};
 
+use crate::{glob, matcher::MatcherKind};
+
+/// Directory names that are never descended into
+pub const DEFAULT_EXCLUDES: &[&str] = &[".git", "node_modules", "target"];
+
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq)]
 pub struct Entry {
     pub path: PathBuf,
     pub min_depth: usize,
     pub max_depth: usize,
+    /// directory names to skip while walking, on top of `DEFAULT_EXCLUDES`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub follow_links: bool,
+    /// walk into dot directories like `.config`, the entry itself is walked
+    /// either way
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub show_hidden: bool,
+    /// sent to freshly created sessions, e.g. `nvim .`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// set in the environment of freshly created sessions
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// list files too, picking one opens a session in its directory
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub include_files: bool,
+    /// run with the picked file appended in sessions created for a file,
+    /// e.g. `nvim`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_command: Option<String>,
+    /// names of the entry's directories are drawn in it, an ansi color code
+    /// `0`-`15` or `#rrggbb` like in the config
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    /// number of panes freshly created sessions start with, `command` runs in
+    /// the first one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub panes: Option<usize>,
+    /// tmux layout the panes are arranged in, e.g. `main-vertical`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layout: Option<String>,
+    /// names sessions instead of the directory name, `{leaf}`, `{parent}` and
+    /// `{root}` are replaced, e.g. `{parent}-{leaf}`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_name: Option<String>,
+    /// window opening a session lands in, created if the session doesn't
+    /// have it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_window: Option<String>,
 }
 
+#[derive(Serialize, Deserialize, Default)]
 pub struct PathList {
+    #[serde(default, rename = "entry")]
     pub entries: Vec<Entry>,
 }
 
+/// Everything read from `~/.config/tmux-fzy/config`
+pub struct Settings {
+    pub colors: Colors,
+    /// moving past either end of the list jumps to the other end
+    pub wrap: bool,
+    /// how many matches are kept while searching, `0` keeps all of them
+    pub max_results: usize,
+    /// symbol in front of the query
+    pub prompt: String,
+    /// title above the list of directories
+    pub results_title: String,
+    /// depths used by `add` when they aren't passed
+    pub default_min_depth: usize,
+    pub default_max_depth: usize,
+    /// also list the directories known to zoxide
+    pub zoxide: bool,
+    /// ask before killing a session from the picker
+    pub confirm_kill: bool,
+    /// rows kept visible between the selection and the edge of the list
+    pub scroll_margin: usize,
+    pub matcher: MatcherKind,
+    /// match the whole path, favoring matches at the start of its segments
+    pub path_segments: bool,
+    pub border_style: BorderStyle,
+    /// start with the query the picker was last closed with
+    pub restore_query: bool,
+    /// tmux server to use instead of the default one, `socket_path` wins
+    /// over `socket_name` like with tmux's `-S` and `-L`
+    pub socket_name: Option<String>,
+    pub socket_path: Option<PathBuf>,
+    /// stop walking after this many directories, `0` for no limit
+    pub max_nodes: usize,
+    /// stop walking after this many seconds, `0` for no limit
+    pub max_time: u64,
+    /// walk into dot directories under every entry, Ctrl-H toggles it
+    pub show_hidden: bool,
+    /// draw the best match at the bottom with the query below the list
+    pub bottom_up: bool,
+    /// put a header above each run of results from the same entry
+    pub group_by_source: bool,
+}
+
+/// Borders around the query and the list, `border` sets their color
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum BorderStyle {
+    #[default]
+    None,
+    Plain,
+    Rounded,
+}
+
+impl FromStr for BorderStyle {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(BorderStyle::None),
+            "plain" => Ok(BorderStyle::Plain),
+            "rounded" => Ok(BorderStyle::Rounded),
+            _ => Err(anyhow::anyhow!("unknown border style '{}'", s)),
+        }
+    }
+}
+
 pub struct Colors {
     pub fg: Color,
     pub border: Color,
@@ -27,62 +146,114 @@ pub struct Colors {
     pub selection: Color,
 }
 
+/// Parses either the toml format or the old `path:|:min_depth:|:max_depth`
+/// format, entries that are not a directory anymore are dropped
 impl FromStr for PathList {
     type Err = anyhow::Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut paths = match toml::from_str::<PathList>(s) {
+            Ok(paths) => paths,
+            Err(_) if s.contains(":|:") => PathList::from_legacy(s)?,
+            Err(err) => return Err(anyhow::anyhow!(err)),
+        };
+        // patterns stay even when they match nothing right now
+        paths
+            .entries
+            .retain(|entry| glob::is_pattern(&entry.path) || entry.path.is_dir());
+        Ok(paths)
+    }
+}
+
+impl Entry {
+    /// Whether a directory with this name should be skipped along with its
+    /// whole subtree
+    pub fn is_excluded(&self, name: &OsStr) -> bool {
+        DEFAULT_EXCLUDES.iter().any(|exclude| name == *exclude)
+            || self.exclude.iter().any(|exclude| name == exclude.as_str())
+    }
+
+    /// `color` parsed, `None` without one or if it's invalid
+    pub fn color(&self) -> Option<Color> {
+        self.color.as_deref().and_then(parse_color)
+    }
+}
+
+/// What `list --json` prints per entry, paths that aren't valid utf8 are
+/// converted lossily instead of failing
+#[derive(Serialize)]
+struct EntryJson<'a> {
+    path: Cow<'a, str>,
+    min_depth: usize,
+    max_depth: usize,
+}
+
+impl PathList {
+    pub fn to_json(&self) -> Result<String, anyhow::Error> {
+        let entries: Vec<EntryJson> = self
+            .entries
+            .iter()
+            .map(|entry| EntryJson {
+                path: entry.path.to_string_lossy(),
+                min_depth: entry.min_depth,
+                max_depth: entry.max_depth,
+            })
+            .collect();
+        serde_json::to_string(&entries).map_err(|e| anyhow::anyhow!(e))
+    }
+
+    /// The entry `dir` was found under, the most specific one if entries
+    /// overlap
+    pub fn entry_for(&self, dir: &Path) -> Option<&Entry> {
+        self.entries
+            .iter()
+            .filter(|entry| glob::contains(&entry.path, dir))
+            .max_by_key(|entry| entry.path.components().count())
+    }
+
+    fn from_legacy(s: &str) -> Result<Self, anyhow::Error> {
         let mut entries = Vec::new();
         for (i, line) in s.lines().enumerate() {
-            let values: Vec<&str> = line.split(":|:").collect();
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let values: Vec<&str> = line.rsplitn(3, ":|:").collect();
 
             if values.len() != 3 {
-                return Err(anyhow::anyhow!("Invalid number of values"));
+                return Err(anyhow::anyhow!(
+                    "Error on line {}, invalid number of values",
+                    i
+                ));
             }
 
-            let path = PathBuf::from_str(values[0]).map_err(|err| anyhow::anyhow!(err))?;
+            let path = PathBuf::from_str(values[2]).map_err(|err| anyhow::anyhow!(err))?;
             let min_depth: usize = values[1]
                 .parse()
                 .map_err(|_| anyhow::anyhow!("Error on line {}, invalid min_depth", i))?;
-            let max_depth: usize = values[2]
+            let max_depth: usize = values[0]
                 .parse()
                 .map_err(|_| anyhow::anyhow!("Error on line {}, invalid max_depth", i))?;
 
-            if path.is_dir() {
-                let path = PathBuf::from_str(values[0])?;
-                entries.push(Entry {
-                    path,
-                    min_depth,
-                    max_depth,
-                })
-            }
+            entries.push(Entry {
+                path,
+                min_depth,
+                max_depth,
+                ..Default::default()
+            })
         }
         Ok(PathList { entries })
     }
 }
 
-impl ToString for PathList {
-    fn to_string(&self) -> String {
-        self.entries
-            .iter()
-            .map(|entry| {
-                format!(
-                    "{}:|:{}:|:{}",
-                    entry.path.to_str().unwrap(),
-                    entry.min_depth,
-                    entry.max_depth
-                )
-            })
-            .collect::<Vec<String>>()
-            .join("\n")
-    }
-}
-
 impl PathList {
-    pub fn insert_row(&mut self, path: PathBuf, min_depth: usize, max_depth: usize) {
-        self.entries.push(Entry {
-            path,
-            min_depth,
-            max_depth,
-        })
+    /// Adds the entry unless its path is already configured, returns whether
+    /// it was added
+    pub fn insert_row(&mut self, entry: Entry) -> bool {
+        if self.entries.iter().any(|e| e.path == entry.path) {
+            return false;
+        }
+        self.entries.push(entry);
+        true
     }
 
     pub fn save_configuration(&self) -> Result<(), anyhow::Error> {
@@ -90,7 +261,7 @@ impl PathList {
             .ok_or(anyhow::anyhow!("Failed to locate the config directory."))?;
         let file_path = paths_dir.join(".tmux-fzy");
 
-        let c = self.to_string();
+        let c = toml::to_string(self).map_err(|e| anyhow::anyhow!(e))?;
 
         let mut file = OpenOptions::new()
             .append(false)
@@ -109,6 +280,109 @@ impl PathList {
         self.entries.retain(|entry| !path.contains(&entry.path));
         Ok(())
     }
+
+    pub fn remove_indices(&mut self, indices: Vec<usize>) -> Result<(), anyhow::Error> {
+        if let Some(i) = indices.iter().find(|&&i| i >= self.entries.len()) {
+            return Err(anyhow::anyhow!(
+                "Index {} is out of range, there are {} entries",
+                i,
+                self.entries.len()
+            ));
+        }
+
+        let mut i = 0;
+        self.entries.retain(|_| {
+            let keep = !indices.contains(&i);
+            i += 1;
+            keep
+        });
+        Ok(())
+    }
+
+    /// Moves the entry at `from` so it ends up at index `to`, shifting the
+    /// ones in between
+    pub fn move_row(&mut self, from: usize, to: usize) -> Result<(), anyhow::Error> {
+        if let Some(i) = [from, to].into_iter().find(|&i| i >= self.entries.len()) {
+            return Err(anyhow::anyhow!(
+                "Index {} is out of range, there are {} entries",
+                i,
+                self.entries.len()
+            ));
+        }
+
+        let entry = self.entries.remove(from);
+        self.entries.insert(to, entry);
+        Ok(())
+    }
+}
+
+impl Settings {
+    /// Applies a single `name=val` line, unknown names and invalid values are
+    /// ignored so a typo never breaks startup
+    fn set(&mut self, name: &str, val: &str) {
+        match name {
+            "fg" => set_if_some(&mut self.colors.fg, parse_color(val)),
+            "border" => set_if_some(&mut self.colors.border, parse_color(val)),
+            "inactive" => set_if_some(&mut self.colors.inactive, parse_color(val)),
+            "active" => set_if_some(&mut self.colors.active, parse_color(val)),
+            "selection" => set_if_some(&mut self.colors.selection, parse_color(val)),
+            "wrap" => set_if_some(&mut self.wrap, val.parse().ok()),
+            "max_results" => set_if_some(&mut self.max_results, val.parse().ok()),
+            "prompt" => self.prompt = val.to_string(),
+            "results_title" => self.results_title = val.to_string(),
+            "default_min_depth" => set_if_some(&mut self.default_min_depth, val.parse().ok()),
+            "default_max_depth" => set_if_some(&mut self.default_max_depth, val.parse().ok()),
+            "zoxide" => set_if_some(&mut self.zoxide, val.parse().ok()),
+            "confirm_kill" => set_if_some(&mut self.confirm_kill, val.parse().ok()),
+            "scroll_margin" => set_if_some(&mut self.scroll_margin, val.parse().ok()),
+            "matcher" => set_if_some(&mut self.matcher, val.parse().ok()),
+            "path_segments" => set_if_some(&mut self.path_segments, val.parse().ok()),
+            "border_style" => set_if_some(&mut self.border_style, val.parse().ok()),
+            "restore_query" => set_if_some(&mut self.restore_query, val.parse().ok()),
+            "bottom_up" => set_if_some(&mut self.bottom_up, val.parse().ok()),
+            "group_by_source" => set_if_some(&mut self.group_by_source, val.parse().ok()),
+            "socket_name" => self.socket_name = Some(val.to_string()),
+            "socket_path" => self.socket_path = Some(PathBuf::from(val)),
+            "show_hidden" => set_if_some(&mut self.show_hidden, val.parse().ok()),
+            "max_nodes" => set_if_some(&mut self.max_nodes, val.parse().ok()),
+            "max_time" => set_if_some(&mut self.max_time, val.parse().ok()),
+            _ => log::warn!("unknown setting '{}'", name),
+        }
+    }
+}
+
+fn set_if_some<T>(field: &mut T, value: Option<T>) {
+    if let Some(value) = value {
+        *field = value;
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            colors: Colors::default(),
+            wrap: false,
+            max_results: 5000,
+            prompt: "".to_string(),
+            results_title: "Results".to_string(),
+            default_min_depth: 0,
+            default_max_depth: 0,
+            zoxide: false,
+            confirm_kill: true,
+            scroll_margin: 3,
+            matcher: MatcherKind::default(),
+            path_segments: false,
+            border_style: BorderStyle::default(),
+            restore_query: false,
+            group_by_source: false,
+            socket_name: None,
+            socket_path: None,
+            bottom_up: false,
+            max_nodes: 0,
+            show_hidden: false,
+            max_time: 0,
+        }
+    }
 }
 
 impl Colors {
@@ -121,6 +395,60 @@ impl Colors {
             selection: Color::LightYellow,
         }
     }
+
+    /// The terminal's own colors everywhere, used when colors are disabled
+    pub fn plain() -> Colors {
+        Colors {
+            fg: Color::Reset,
+            border: Color::Reset,
+            inactive: Color::Reset,
+            active: Color::Reset,
+            selection: Color::Reset,
+        }
+    }
+}
+
+/// Turns off colors for everything written through crossterm, which includes
+/// the `Stylize` calls when printing as well as the picker
+pub fn disable_colors() {
+    crossterm::style::force_color_output(false);
+}
+
+/// Whether output is colored, false when `NO_COLOR` is set or after
+/// `disable_colors`
+pub fn colors_enabled() -> bool {
+    !Colored::ansi_color_disabled_memoized()
+}
+
+/// `styled` as is when colors are enabled and unstyled otherwise. crossterm
+/// leaves out the colors on its own but still prints the reset codes, every
+/// styled print goes through here to get plain text
+pub fn paint<D: Display>(styled: StyledContent<D>) -> StyledContent<D> {
+    paint_with(styled, colors_enabled())
+}
+
+/// `paint` with the choice made by the caller instead of the global setting
+pub fn paint_with<D: Display>(mut styled: StyledContent<D>, colors: bool) -> StyledContent<D> {
+    if !colors {
+        *styled.style_mut() = ContentStyle::new();
+    }
+    styled
+}
+
+/// Parses an ansi color code `0`-`15` or a `#rrggbb` hex color
+fn parse_color(val: &str) -> Option<Color> {
+    if let Ok(i) = val.parse::<u8>() {
+        return int_to_ansi_colors(i);
+    }
+
+    let hex = val.strip_prefix('#')?;
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let r = u8::from_str_radix(&hex[0..2], 16).ok()?;
+    let g = u8::from_str_radix(&hex[2..4], 16).ok()?;
+    let b = u8::from_str_radix(&hex[4..6], 16).ok()?;
+    Some(Color::Rgb(r, g, b))
 }
 
 fn int_to_ansi_colors(i: u8) -> Option<Color> {
@@ -145,6 +473,10 @@ fn int_to_ansi_colors(i: u8) -> Option<Color> {
     }
 }
 
+fn is_false(b: &bool) -> bool {
+    !b
+}
+
 fn is_absolute_path(path: OsString) -> Option<PathBuf> {
     let path = PathBuf::from(path);
     if path.is_absolute() {
@@ -155,6 +487,11 @@ fn is_absolute_path(path: OsString) -> Option<PathBuf> {
 }
 
 fn get_paths_dir(from_home: &str) -> Option<PathBuf> {
+    // tests never read or write the developer's cache and config
+    if cfg!(test) {
+        let dir = env::temp_dir().join(format!("tmux-fzy-test-{}", std::process::id()));
+        return Some(dir.join(from_home));
+    }
     env::var_os("XDG_CACHE_HOME")
         .and_then(is_absolute_path)
         .or_else(|| {
@@ -164,16 +501,48 @@ fn get_paths_dir(from_home: &str) -> Option<PathBuf> {
         })
 }
 
-fn init_config(path: &PathBuf) -> Result<(), anyhow::Error> {
-    let dir = path.parent().unwrap();
-    if !dir.exists() {
-        fs::create_dir(dir).map_err(|e| anyhow::anyhow!(e))?;
+/// Location of a file inside the cache directory
+pub fn cache_path(file_name: &str) -> Option<PathBuf> {
+    get_paths_dir(".cache").map(|dir| dir.join(file_name))
+}
+
+/// Location of the paths file, unlike `get_paths_file` it isn't created
+pub fn paths_file() -> Option<PathBuf> {
+    cache_path(".tmux-fzy")
+}
+
+/// Location of the `key=value` settings file
+pub fn config_file() -> Option<PathBuf> {
+    get_paths_dir(".config/tmux-fzy").map(|dir| dir.join("config"))
+}
+
+/// Query the picker was closed with, see `restore_query`
+pub fn load_last_query() -> Option<String> {
+    let contents = fs::read_to_string(cache_path(".tmux-fzy-query")?).ok()?;
+    Some(contents.trim_end_matches('\n').to_string())
+}
+
+pub fn save_last_query(query: &str) -> Result<(), anyhow::Error> {
+    let file_path = cache_path(".tmux-fzy-query")
+        .ok_or(anyhow::anyhow!("Failed to locate the cache directory."))?;
+    fs::write(file_path, query).map_err(|e| anyhow::anyhow!(e))?;
+    Ok(())
+}
+
+/// Creates an empty file at `path` along with any missing parent directories,
+/// `XDG_CACHE_HOME` may point somewhere that doesn't exist yet
+fn init_config(path: &Path) -> Result<(), anyhow::Error> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", dir.display(), e))?;
     }
-    File::create(path).map_err(|e| anyhow::anyhow!(e))?;
+    File::create(path)
+        .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", path.display(), e))?;
     Ok(())
 }
 
-pub fn get_paths() -> Result<PathList, anyhow::Error> {
+/// Location of the paths file, it is created if it doesn't exist yet
+pub fn get_paths_file() -> Result<PathBuf, anyhow::Error> {
     let config_dir =
         get_paths_dir(".cache").ok_or(anyhow::anyhow!("Failed to locate the config directory."))?;
 
@@ -181,41 +550,107 @@ pub fn get_paths() -> Result<PathList, anyhow::Error> {
     if !file_path.exists() {
         init_config(&file_path)?;
     }
+    Ok(file_path)
+}
 
-    let mut file = File::open(&file_path).map_err(|e| anyhow::anyhow!(e))?;
+/// When the paths file was last written, `None` if that can't be read
+pub fn paths_modified() -> Option<SystemTime> {
+    fs::metadata(paths_file()?)
+        .and_then(|meta| meta.modified())
+        .ok()
+}
+
+pub fn get_paths() -> Result<PathList, anyhow::Error> {
+    read_paths(&get_paths_file()?)
+}
+
+fn read_paths(file_path: &Path) -> Result<PathList, anyhow::Error> {
+    let mut file = File::open(file_path).map_err(|e| anyhow::anyhow!(e))?;
     let mut contents = String::new();
     file.read_to_string(&mut contents)
         .map_err(|e| anyhow::anyhow!(e))?;
     let paths = PathList::from_str(&contents).map_err(|e| anyhow::anyhow!(e))?;
+    log::info!(
+        "read {} entries from {}",
+        paths.entries.len(),
+        file_path.display()
+    );
     Ok(paths)
 }
 
-pub fn init_colors() -> Colors {
-    let mut colors = Colors::default();
-    let config_dir = {
-        if let Some(path) = get_paths_dir(".config/tmux-fzy") {
+/// Opens the paths file in `$EDITOR` (or `vi`) and checks that it still
+/// parses once the editor exits
+pub fn edit_paths() -> Result<(), anyhow::Error> {
+    let file_path = get_paths_file()?;
+    let editor = env::var("EDITOR")
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string());
+
+    // `$EDITOR` may carry arguments, e.g. `code --wait`
+    let mut args = editor.split_whitespace();
+    let program = args.next().unwrap_or("vi");
+    let status = Command::new(program)
+        .args(args)
+        .arg(&file_path)
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("Failed to launch editor '{}': {}", program, e))?
+        .wait()
+        .map_err(|e| anyhow::anyhow!(e))?;
+
+    if !status.success() {
+        return Err(anyhow::anyhow!(
+            "Editor '{}' exited with {}",
+            program,
+            status
+        ));
+    }
+
+    let contents = fs::read_to_string(&file_path).map_err(|e| anyhow::anyhow!(e))?;
+    PathList::from_str(&contents).map_err(|e| {
+        anyhow::anyhow!(
+            "{} is no longer valid, run `tmux-fzy edit` again to fix it: {}",
+            file_path.display(),
+            e
+        )
+    })?;
+    Ok(())
+}
+
+pub fn init_settings() -> Settings {
+    let mut settings = Settings::default();
+    let file_path = {
+        if let Some(path) = config_file() {
             path
         } else {
-            return colors;
+            return settings;
         }
     };
 
-    let file_path = config_dir.join("config");
     if !file_path.exists() {
-        return colors;
+        log::info!("no config at {}, using the defaults", file_path.display());
+        return settings;
     }
 
     let mut file = {
         match File::open(&file_path) {
             Ok(file) => file,
-            Err(_) => return colors,
+            Err(err) => {
+                log::warn!("failed to open {}: {}", file_path.display(), err);
+                return settings;
+            }
         }
     };
 
     let mut contents = String::new();
-    if file.read_to_string(&mut contents).is_err() {
-        return colors;
+    if let Err(err) = file.read_to_string(&mut contents) {
+        log::warn!("failed to read {}: {}", file_path.display(), err);
+        return settings;
     };
+    log::info!("read settings from {}", file_path.display());
 
     for line in contents.lines() {
         if line.is_empty() {
@@ -223,23 +658,154 @@ pub fn init_colors() -> Colors {
         }
         let parts = line.split_once('=');
         if let Some((name, val)) = parts {
-            let name = name.trim();
-            let val = val.trim();
-            if let Ok(value) = val.parse::<u8>() {
-                let value = int_to_ansi_colors(value);
-                if let Some(value) = value {
-                    match name {
-                        "fg" => colors.fg = value,
-                        "border" => colors.border = value,
-                        "inactive" => colors.inactive = value,
-                        "active" => colors.active = value,
-                        "selection" => colors.selection = value,
-                        _ => {}
-                    }
-                }
-            }
+            settings.set(name.trim(), val.trim());
+        }
+    }
+
+    settings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{make_dirs, temp_dir};
+
+    fn depths(paths: &PathList) -> Vec<(PathBuf, usize, usize)> {
+        paths
+            .entries
+            .iter()
+            .map(|entry| (entry.path.clone(), entry.min_depth, entry.max_depth))
+            .collect()
+    }
+
+    fn list(names: &[&str]) -> PathList {
+        PathList {
+            entries: names
+                .iter()
+                .map(|name| Entry {
+                    path: PathBuf::from(name),
+                    ..Default::default()
+                })
+                .collect(),
         }
     }
 
-    colors
+    fn names(paths: &PathList) -> Vec<&str> {
+        paths
+            .entries
+            .iter()
+            .map(|entry| entry.path.to_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn move_rows() {
+        let mut paths = list(&["a", "b", "c", "d"]);
+        paths.move_row(0, 2).unwrap();
+        assert_eq!(names(&paths), ["b", "c", "a", "d"]);
+        paths.move_row(3, 0).unwrap();
+        assert_eq!(names(&paths), ["d", "b", "c", "a"]);
+        paths.move_row(1, 1).unwrap();
+        assert_eq!(names(&paths), ["d", "b", "c", "a"]);
+
+        assert!(paths.move_row(4, 0).is_err());
+        assert!(paths.move_row(0, 4).is_err());
+        assert_eq!(names(&paths), ["d", "b", "c", "a"]);
+    }
+
+    #[test]
+    fn remove_rows() {
+        let mut paths = list(&["a", "b", "c", "d"]);
+        paths.remove_indices(vec![3, 1]).unwrap();
+        assert_eq!(names(&paths), ["a", "c"]);
+
+        // nothing is removed when any index is out of range
+        assert!(paths.remove_indices(vec![0, 2]).is_err());
+        assert_eq!(names(&paths), ["a", "c"]);
+        paths.remove_indices(vec![0, 0, 1]).unwrap();
+        assert!(paths.entries.is_empty());
+    }
+
+    #[test]
+    fn colors() {
+        assert_eq!(parse_color("1"), Some(Color::Red));
+        assert_eq!(parse_color("15"), Some(Color::White));
+        assert_eq!(parse_color("#00ff7F"), Some(Color::Rgb(0, 255, 127)));
+        assert_eq!(parse_color("16"), None);
+        assert_eq!(parse_color("-1"), None);
+        assert_eq!(parse_color("00ff7f"), None);
+        assert_eq!(parse_color("#00ff7"), None);
+        assert_eq!(parse_color("#00ff7fa"), None);
+        assert_eq!(parse_color("#+1+2+3"), None);
+        assert_eq!(parse_color("#ééé"), None);
+        assert_eq!(parse_color("red"), None);
+    }
+
+    #[test]
+    fn paths_file_in_missing_dirs() {
+        let root = temp_dir("config-init");
+        let file_path = root.join("not/there/yet/.tmux-fzy");
+        init_config(&file_path).unwrap();
+        assert!(read_paths(&file_path).unwrap().entries.is_empty());
+
+        // a file where a directory should be is reported
+        let blocked = root.join("not/there/yet/.tmux-fzy/.tmux-fzy");
+        let err = init_config(&blocked).unwrap_err();
+        assert!(err.to_string().starts_with("Failed to create"));
+        _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn toml_round_trip() {
+        let root = temp_dir("config-toml");
+        make_dirs(&root, &["work", "misc"]);
+        let paths = PathList {
+            entries: vec![
+                Entry {
+                    path: root.join("work"),
+                    min_depth: 1,
+                    max_depth: 2,
+                    exclude: vec!["vendor".to_string()],
+                    command: Some("nvim .".to_string()),
+                    ..Default::default()
+                },
+                Entry {
+                    path: root.join("misc"),
+                    ..Default::default()
+                },
+                Entry {
+                    path: root.join("gone"),
+                    ..Default::default()
+                },
+            ],
+        };
+
+        let parsed = PathList::from_str(&toml::to_string(&paths).unwrap()).unwrap();
+        // directories that don't exist anymore are dropped
+        assert_eq!(depths(&parsed), depths(&paths)[..2]);
+        assert_eq!(parsed.entries[0].exclude, ["vendor"]);
+        assert_eq!(parsed.entries[0].command.as_deref(), Some("nvim ."));
+        _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn legacy_round_trip() {
+        let root = temp_dir("config-legacy");
+        make_dirs(&root, &["work", "odd:|:name"]);
+        let legacy = format!(
+            "{}:|:1:|:3\n\n{}:|:0:|:2\n",
+            root.join("work").display(),
+            root.join("odd:|:name").display()
+        );
+
+        let parsed = PathList::from_str(&legacy).unwrap();
+        let expected = vec![(root.join("work"), 1, 3), (root.join("odd:|:name"), 0, 2)];
+        assert_eq!(depths(&parsed), expected);
+        // once saved it's read back as toml
+        let saved = toml::to_string(&parsed).unwrap();
+        assert_eq!(depths(&PathList::from_str(&saved).unwrap()), expected);
+
+        assert!(PathList::from_str("only:|:1").is_err());
+        _ = fs::remove_dir_all(root);
+    }
 }
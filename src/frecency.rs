@@ -0,0 +1,93 @@
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    fs,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::cache_path;
+
+/// Upper bound on the number of directories remembered
+const MAX_VISITS: usize = 500;
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+struct Visit {
+    count: u32,
+    /// unix timestamp in seconds
+    last_used: u64,
+}
+
+/// Recently and frequently opened directories, keyed by full path
+#[derive(Default)]
+pub struct Frecency {
+    visits: HashMap<String, Visit>,
+}
+
+impl Frecency {
+    /// Loads the store, a missing or broken file is just an empty store
+    pub fn load() -> Frecency {
+        let visits = cache_path(".tmux-fzy-history")
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|contents| toml::from_str(&contents).ok())
+            .unwrap_or_default();
+        Frecency { visits }
+    }
+
+    /// Score used to order the list while the query is empty, 0 for
+    /// directories that were never opened
+    pub fn score(&self, fullpath: &str) -> i64 {
+        let Some(visit) = self.visits.get(fullpath) else {
+            return 0;
+        };
+        let age = now().saturating_sub(visit.last_used);
+        let weight = match age {
+            age if age < 60 * 60 => 8,
+            age if age < 60 * 60 * 24 => 4,
+            age if age < 60 * 60 * 24 * 7 => 2,
+            _ => 1,
+        };
+        visit.count as i64 * weight
+    }
+
+    fn save(&mut self) -> Result<(), anyhow::Error> {
+        self.visits.retain(|path, _| Path::new(path).exists());
+        if self.visits.len() > MAX_VISITS {
+            let mut visits: Vec<(String, Visit)> = self.visits.drain().collect();
+            visits.sort_unstable_by_key(|(_, visit)| Reverse(visit.last_used));
+            visits.truncate(MAX_VISITS);
+            self.visits = visits.into_iter().collect();
+        }
+
+        let file_path = cache_path(".tmux-fzy-history")
+            .ok_or(anyhow::anyhow!("Failed to locate the cache directory."))?;
+        let contents = toml::to_string(&self.visits).map_err(|e| anyhow::anyhow!(e))?;
+        fs::write(file_path, contents).map_err(|e| anyhow::anyhow!(e))?;
+        Ok(())
+    }
+}
+
+/// Records a visit to `fullpath`, failures are ignored since this is only
+/// used for ordering
+pub fn record(fullpath: &str) {
+    let mut frecency = Frecency::load();
+    let visit = frecency
+        .visits
+        .entry(fullpath.to_string())
+        .or_insert(Visit {
+            count: 0,
+            last_used: 0,
+        });
+    visit.count = visit.count.saturating_add(1);
+    visit.last_used = now();
+    _ = frecency.save();
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
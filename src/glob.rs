@@ -0,0 +1,230 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use ::glob::{MatchOptions, Pattern};
+
+/// `*` and `?` don't match a `/` or a leading `.`, so hidden directories
+/// have to be asked for explicitly
+const OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: true,
+};
+
+/// Whether `path` needs expanding, i.e. starts with `~` or has any of the
+/// `*`, `?`, `[...]` or `{a,b}` patterns in it
+pub fn is_pattern(path: &Path) -> bool {
+    path.to_str().is_some_and(|path| {
+        path == "~" || path.starts_with("~/") || path.contains(['*', '?', '[', '{'])
+    })
+}
+
+/// The existing directories `path` matches, sorted. Paths that aren't a
+/// pattern are returned as they are, a pattern without matches gives nothing.
+/// An existing directory is taken literally, e.g. `~/music/Album [2020]`
+pub fn expand(path: &Path) -> Vec<PathBuf> {
+    if !is_pattern(path) {
+        return vec![path.to_path_buf()];
+    }
+
+    let mut dirs: Vec<PathBuf> = alternatives(path)
+        .iter()
+        .flat_map(|pattern| match Path::new(pattern).is_dir() {
+            true => vec![PathBuf::from(pattern)],
+            false => glob_dirs(pattern),
+        })
+        .filter(|dir| dir.is_dir())
+        .collect();
+    dirs.sort();
+    dirs.dedup();
+    dirs
+}
+
+/// What `pattern` matches, an invalid one like `a[` matches nothing
+fn glob_dirs(pattern: &str) -> Vec<PathBuf> {
+    // the walk itself would skip every hidden name, even for `.config*`
+    let walk = MatchOptions {
+        require_literal_leading_dot: false,
+        ..OPTIONS
+    };
+    let (Ok(paths), Ok(matcher)) = (::glob::glob_with(pattern, walk), Pattern::new(pattern)) else {
+        return Vec::new();
+    };
+    paths
+        .filter_map(Result::ok)
+        .filter(|path| {
+            // a leading `.` in the pattern also gets `.` and `..` listed
+            let special = path.file_name().is_none() || path.to_string_lossy().ends_with("/.");
+            !special && matcher.matches_path_with(path, OPTIONS)
+        })
+        .collect()
+}
+
+/// Whether `dir` is one of the directories `path` matches or inside one
+pub fn contains(path: &Path, dir: &Path) -> bool {
+    base(path, dir).is_some()
+}
+
+/// The directory `path` matches that `dir` is or is inside of
+pub fn base(path: &Path, dir: &Path) -> Option<PathBuf> {
+    if !is_pattern(path) {
+        return dir.starts_with(path).then(|| path.to_path_buf());
+    }
+
+    alternatives(path).iter().find_map(|pattern| {
+        if Path::new(pattern).is_dir() {
+            return dir.starts_with(pattern).then(|| PathBuf::from(pattern));
+        }
+        // the pattern matches as many leading components of `dir` as it has
+        let len = Path::new(pattern).components().count();
+        let base: PathBuf = dir.components().take(len).collect();
+        let matched = base.components().count() == len
+            && Pattern::new(pattern).is_ok_and(|pattern| pattern.matches_path_with(&base, OPTIONS));
+        matched.then_some(base)
+    })
+}
+
+/// Every pattern `path` stands for once `~` and braces are expanded, braces
+/// in the name of an existing directory are left alone
+fn alternatives(path: &Path) -> Vec<String> {
+    let Some(path) = path.to_str() else {
+        return Vec::new();
+    };
+    let path = match (path.strip_prefix('~'), env::var("HOME")) {
+        (Some(rest), Ok(home)) if rest.is_empty() || rest.starts_with('/') => {
+            format!("{}{}", home, rest)
+        }
+        _ => path.to_string(),
+    };
+    if Path::new(&path).is_dir() {
+        return vec![path];
+    }
+    expand_braces(&path)
+}
+
+/// `a{b,c}d` into `abd` and `acd`, nested braces are expanded as well
+fn expand_braces(pattern: &str) -> Vec<String> {
+    let Some(open) = pattern.find('{') else {
+        return vec![pattern.to_string()];
+    };
+
+    let mut depth = 0;
+    let mut commas = Vec::new();
+    let mut close = None;
+    for (i, c) in pattern[open..].char_indices().map(|(i, c)| (open + i, c)) {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    close = Some(i);
+                    break;
+                }
+            }
+            ',' if depth == 1 => commas.push(i),
+            _ => {}
+        }
+    }
+    // an unmatched brace is taken literally
+    let Some(close) = close else {
+        return vec![pattern.to_string()];
+    };
+
+    let (prefix, suffix) = (&pattern[..open], &pattern[close + 1..]);
+    let mut bounds = vec![open];
+    bounds.extend(commas);
+    bounds.push(close);
+    bounds
+        .windows(2)
+        .flat_map(|bound| {
+            let choice = &pattern[bound[0] + 1..bound[1]];
+            expand_braces(&format!("{}{}{}", prefix, choice, suffix))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{make_dirs, temp_dir};
+    use std::os::unix::ffi::OsStrExt;
+
+    #[test]
+    fn braces() {
+        assert_eq!(expand_braces("a{b,c}d"), ["abd", "acd"]);
+        assert_eq!(expand_braces("{a,b{c,d}}"), ["a", "bc", "bd"]);
+        assert_eq!(expand_braces("x{,y}"), ["x", "xy"]);
+        assert_eq!(expand_braces("a{b"), ["a{b"]);
+        assert_eq!(expand_braces("plain"), ["plain"]);
+    }
+
+    #[test]
+    fn wildcards() {
+        let root = temp_dir("glob-wildcards");
+        make_dirs(
+            &root,
+            &["v10", "v2x", "va", ".vim", "v1/v2", "v1/.v3", "a["],
+        );
+        let names = |pattern: &str| -> Vec<String> {
+            expand(&root.join(pattern))
+                .iter()
+                .map(|dir| dir.strip_prefix(&root).unwrap().display().to_string())
+                .collect()
+        };
+
+        // a name that isn't utf8 next to the matches is skipped
+        std::fs::create_dir(root.join(std::ffi::OsStr::from_bytes(b"v\xff"))).unwrap();
+        assert_eq!(names("v[0-9]*"), ["v1", "v10", "v2x"]);
+        assert_eq!(names("v[!0-9]"), ["va"]);
+        assert_eq!(names("v?"), ["v1", "va"]);
+        // `*` stays within a name and skips hidden directories
+        assert_eq!(names("v*2"), Vec::<String>::new());
+        assert_eq!(names("v*/v?"), ["v1/v2"]);
+        assert_eq!(names(".v*"), [".vim"]);
+        assert_eq!(names(".*"), [".vim"]);
+        assert_eq!(names("v1/.*"), ["v1/.v3"]);
+        // an existing name wins, an invalid pattern matches nothing
+        assert_eq!(names("a["), ["a["]);
+        assert!(names("b[").is_empty());
+
+        assert!(contains(&root.join("v*"), &root.join("v1/v2")));
+        assert!(!contains(&root.join("v*"), &root.join(".vim")));
+        _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn expands_to_existing_dirs() {
+        let root = temp_dir("glob-expand");
+        make_dirs(&root, &["work/a", "work/b", "work/.hidden", "misc"]);
+        std::fs::write(root.join("work/file"), "").unwrap();
+
+        let found = expand(&root.join("work/*"));
+        assert_eq!(found, [root.join("work/a"), root.join("work/b")]);
+        assert_eq!(expand(&root.join("{work,misc}")).len(), 2);
+        assert!(expand(&root.join("nothing/*")).is_empty());
+        assert!(expand(&root.join("work/z*")).is_empty());
+
+        assert!(contains(&root.join("work/*"), &root.join("work/a/src")));
+        assert_eq!(
+            base(&root.join("work/*"), &root.join("work/a/src")),
+            Some(root.join("work/a"))
+        );
+        assert!(!contains(&root.join("work/*"), &root.join("misc")));
+        _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn existing_dir_is_literal() {
+        let root = temp_dir("glob-literal");
+        make_dirs(&root, &["Album [2020]/disc", "Album 2", "a{b,c}"]);
+
+        let album = root.join("Album [2020]");
+        assert_eq!(expand(&album), std::slice::from_ref(&album));
+        assert!(contains(&album, &album.join("disc")));
+        assert!(!contains(&album, &root.join("Album 2")));
+        assert_eq!(expand(&root.join("a{b,c}")), [root.join("a{b,c}")]);
+        _ = std::fs::remove_dir_all(root);
+    }
+}
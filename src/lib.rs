@@ -0,0 +1,25 @@
+//! A simple project manager for tmux. The binary is a thin wrapper around
+//! `run`, `search_paths` gives the picker's results without the picker
+
+mod cli;
+mod config;
+mod frecency;
+mod glob;
+mod logger;
+mod matcher;
+mod pins;
+mod startup;
+#[cfg(test)]
+mod test_util;
+mod tmux;
+mod tui;
+mod tui_components;
+mod zoxide;
+
+#[doc(hidden)]
+pub use config::paint;
+pub use config::{Entry, PathList};
+pub use frecency::Frecency;
+#[doc(hidden)]
+pub use startup::run;
+pub use tui::search_paths;
@@ -0,0 +1,70 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::Path,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+/// Environment variable naming the log file, `--log` takes precedence
+pub const LOG_ENV: &str = "TMUX_FZY_LOG";
+
+/// Appends one line per record to a file, the picker owns the terminal so
+/// nothing can be printed while it runs
+struct FileLogger {
+    file: Mutex<File>,
+}
+
+impl Log for FileLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        // dependencies log through the same facade
+        metadata.target().starts_with(env!("CARGO_CRATE_NAME"))
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        if let Ok(mut file) = self.file.lock() {
+            _ = writeln!(
+                file,
+                "{}.{:03} {:<5} {}: {}",
+                now.as_secs(),
+                now.subsec_millis(),
+                record.level(),
+                record.target(),
+                record.args()
+            );
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut file) = self.file.lock() {
+            _ = file.flush();
+        }
+    }
+}
+
+/// Starts logging to `path`, without one nothing is installed and every log
+/// call is skipped at the level check
+pub fn init(path: Option<&Path>) -> Result<(), anyhow::Error> {
+    let Some(path) = path else {
+        return Ok(());
+    };
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| anyhow::anyhow!("Failed to open log file {}: {}", path.display(), e))?;
+    log::set_boxed_logger(Box::new(FileLogger {
+        file: Mutex::new(file),
+    }))
+    .map_err(|e| anyhow::anyhow!(e))?;
+    log::set_max_level(LevelFilter::Debug);
+    Ok(())
+}
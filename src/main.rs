@@ -1,20 +1,18 @@
 use crossterm::style::Stylize;
 use crossterm::{execute, style::Print};
-use startup::run;
+use std::process::ExitCode;
+use tmux_fzy::{paint, run};
 
-mod cli;
-mod config;
-mod startup;
-mod tmux;
-mod tui;
-mod tui_components;
-
-fn main() -> Result<(), anyhow::Error> {
-    if let Err(err) = run() {
-        execute!(std::io::stderr(), Print("Error: ".red()))?;
-        for cause in err.chain() {
-            execute!(std::io::stderr(), Print(cause), Print("\n"))?;
+fn main() -> ExitCode {
+    match run() {
+        Ok(code) => code,
+        Err(err) => {
+            log::error!("{:#}", err);
+            _ = execute!(std::io::stderr(), Print(paint("Error: ".red())));
+            for cause in err.chain() {
+                _ = execute!(std::io::stderr(), Print(cause), Print("\n"));
+            }
+            ExitCode::FAILURE
         }
     }
-    Ok(())
 }
@@ -0,0 +1,210 @@
+use std::str::FromStr;
+
+use fuzzy_matcher::{clangd::ClangdMatcher, skim::SkimMatcherV2, FuzzyMatcher};
+
+/// Which algorithm matches query tokens, picked with `matcher=` in the config
+#[derive(Clone, Copy, Default)]
+pub enum MatcherKind {
+    #[default]
+    Skim,
+    Clangd,
+    Substring,
+}
+
+impl FromStr for MatcherKind {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skim" => Ok(MatcherKind::Skim),
+            "clangd" => Ok(MatcherKind::Clangd),
+            "substring" => Ok(MatcherKind::Substring),
+            _ => Err(anyhow::anyhow!("unknown matcher '{}'", s)),
+        }
+    }
+}
+
+/// Extra score for every matched char that starts a path segment
+const SEGMENT_START_BONUS: i64 = 32;
+
+/// Extra score when the name starts with a fuzzy token, `api` then puts a
+/// directory named `api` above `a-big-project-index`
+const NAME_PREFIX_BONUS: i64 = 32;
+
+/// Extra score when a fuzzy token appears in the name as is, not at its start
+const NAME_SUBSTRING_BONUS: i64 = 16;
+
+enum Backend {
+    Skim(Box<SkimMatcherV2>),
+    Clangd(Box<ClangdMatcher>),
+    Substring,
+}
+
+pub struct Matcher {
+    backend: Backend,
+    /// match against the whole path rather than the name, see `path_indices`
+    pub path_segments: bool,
+}
+
+impl Matcher {
+    pub fn new(kind: MatcherKind, path_segments: bool) -> Matcher {
+        let backend = match kind {
+            MatcherKind::Skim => Backend::Skim(Box::default()),
+            MatcherKind::Clangd => Backend::Clangd(Box::new(ClangdMatcher::default().smart_case())),
+            MatcherKind::Substring => Backend::Substring,
+        };
+        Matcher {
+            backend,
+            path_segments,
+        }
+    }
+
+    /// Score and matched char positions of `pattern` in `choice`, `None` if it
+    /// doesn't match. Every backend uses smart case by comparing one char at
+    /// a time, `choice` is never case folded as a whole so the positions
+    /// always point at its chars as they are
+    pub fn fuzzy_indices(&self, choice: &str, pattern: &str) -> Option<(i64, Vec<usize>)> {
+        match &self.backend {
+            Backend::Skim(matcher) => matcher.fuzzy_indices(choice, pattern),
+            Backend::Clangd(matcher) => matcher.fuzzy_indices(choice, pattern),
+            Backend::Substring => substring_indices(choice, pattern),
+        }
+    }
+
+    /// Like `fuzzy_indices` on a whole path, with a bonus for every char
+    /// matched at the start of a segment so `wp` prefers `work/projects` over
+    /// `swap`
+    pub fn path_indices(&self, path: &str, pattern: &str) -> Option<(i64, Vec<usize>)> {
+        let (score, indices) = self.fuzzy_indices(path, pattern)?;
+        // a char starts a segment when it follows a `/`
+        let starts: Vec<bool> = path
+            .chars()
+            .scan('/', |prev, c| {
+                Some(std::mem::replace(prev, c) == '/' && c != '/')
+            })
+            .collect();
+        let boundaries = indices
+            .iter()
+            .filter(|&&i| starts.get(i) == Some(&true))
+            .count() as i64;
+        Some((score + boundaries * SEGMENT_START_BONUS, indices))
+    }
+}
+
+/// Added to the score of a fuzzy `pattern` that matched, ignoring case. Skim
+/// scores about 20 per matched char so this decides between close matches
+/// without overriding a clearly better one
+pub fn name_bonus(name: &str, pattern: &str) -> i64 {
+    let name = name.to_lowercase();
+    let pattern = pattern.to_lowercase();
+    if name.starts_with(&pattern) {
+        NAME_PREFIX_BONUS
+    } else if name.contains(&pattern) {
+        NAME_SUBSTRING_BONUS
+    } else {
+        0
+    }
+}
+
+/// First occurrence of `pattern` in `choice`, earlier matches in shorter
+/// names score higher
+fn substring_indices(choice: &str, pattern: &str) -> Option<(i64, Vec<usize>)> {
+    let case_sensitive = pattern.chars().any(char::is_uppercase);
+    let eq = |a: char, b: char| {
+        if case_sensitive {
+            a == b
+        } else {
+            a.to_lowercase().eq(b.to_lowercase())
+        }
+    };
+
+    let choice: Vec<char> = choice.chars().collect();
+    let pattern: Vec<char> = pattern.chars().collect();
+    if pattern.len() > choice.len() {
+        return None;
+    }
+    let start = (0..=choice.len() - pattern.len()).find(|&start| {
+        pattern
+            .iter()
+            .zip(&choice[start..])
+            .all(|(&p, &c)| eq(p, c))
+    })?;
+
+    let score = -((start * 100 + choice.len()) as i64);
+    Some((score, (start..start + pattern.len()).collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [MatcherKind; 3] = [
+        MatcherKind::Skim,
+        MatcherKind::Clangd,
+        MatcherKind::Substring,
+    ];
+
+    #[test]
+    fn kinds() {
+        for name in ["skim", "clangd", "substring"] {
+            assert!(name.parse::<MatcherKind>().is_ok());
+        }
+        assert!("fzf".parse::<MatcherKind>().is_err());
+    }
+
+    #[test]
+    fn backends_agree() {
+        for kind in KINDS {
+            let matcher = Matcher::new(kind, false);
+            let indices = |choice, pattern| {
+                matcher
+                    .fuzzy_indices(choice, pattern)
+                    .map(|(_, indices)| indices)
+            };
+            assert_eq!(indices("projects", "ject"), Some(vec![3, 4, 5, 6]));
+            assert_eq!(indices("projects", "JECT"), None);
+            assert_eq!(indices("Projects", "Proj"), Some(vec![0, 1, 2, 3]));
+            assert_eq!(indices("projects", "Proj"), None);
+            assert_eq!(indices("projects", "xyz"), None);
+            assert!(matcher.fuzzy_indices("projects", "").is_some());
+        }
+
+        // only the fuzzy backends skip chars
+        let matched: Vec<bool> = KINDS
+            .map(|kind| {
+                Matcher::new(kind, false)
+                    .fuzzy_indices("projects", "pjs")
+                    .is_some()
+            })
+            .to_vec();
+        assert_eq!(matched, [true, true, false]);
+    }
+
+    #[test]
+    fn substring_prefers_earlier_matches_in_shorter_names() {
+        let score = |choice| substring_indices(choice, "app").unwrap().0;
+        assert!(score("app") > score("my-app"));
+        assert!(score("app-x") > score("app-extra"));
+    }
+
+    #[test]
+    fn segment_starts_rank_higher() {
+        for kind in KINDS {
+            let matcher = Matcher::new(kind, true);
+            let score = |path| matcher.path_indices(path, "wp").map(|(score, _)| score);
+            match kind {
+                // `wp` isn't in either path as is
+                MatcherKind::Substring => {
+                    assert_eq!(score("/home/work/projects"), None);
+                    assert_eq!(score("/home/swap/temp"), None);
+                }
+                _ => assert!(score("/home/work/projects") > score("/home/swap/temp")),
+            }
+        }
+
+        let matcher = Matcher::new(MatcherKind::Skim, true);
+        let (score, indices) = matcher.path_indices("/w/p", "wp").unwrap();
+        let (plain, _) = matcher.fuzzy_indices("/w/p", "wp").unwrap();
+        assert_eq!(indices, [1, 3]);
+        assert_eq!(score, plain + 2 * SEGMENT_START_BONUS);
+    }
+}
@@ -0,0 +1,47 @@
+use std::{collections::BTreeSet, fs};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::cache_path;
+
+/// Directories that are always listed above everything else, keyed by full
+/// path
+#[derive(Serialize, Deserialize, Default)]
+pub struct Pins {
+    #[serde(default)]
+    pinned: BTreeSet<String>,
+}
+
+impl Pins {
+    /// Loads the pins, a missing or broken file means nothing is pinned
+    pub fn load() -> Pins {
+        cache_path(".tmux-fzy-pins")
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|contents| toml::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn contains(&self, fullpath: &str) -> bool {
+        self.pinned.contains(fullpath)
+    }
+
+    /// Pins `fullpath` or unpins it if it already was, returns whether it is
+    /// pinned now
+    pub fn toggle(&mut self, fullpath: &str) -> Result<bool, anyhow::Error> {
+        let pinned = if self.pinned.remove(fullpath) {
+            false
+        } else {
+            self.pinned.insert(fullpath.to_string())
+        };
+        self.save()?;
+        Ok(pinned)
+    }
+
+    fn save(&self) -> Result<(), anyhow::Error> {
+        let file_path = cache_path(".tmux-fzy-pins")
+            .ok_or(anyhow::anyhow!("Failed to locate the cache directory."))?;
+        let contents = toml::to_string(self).map_err(|e| anyhow::anyhow!(e))?;
+        fs::write(file_path, contents).map_err(|e| anyhow::anyhow!(e))?;
+        Ok(())
+    }
+}
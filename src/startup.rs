@@ -1,4 +1,6 @@
-use clap::Parser;
+use std::{io::Write, path::PathBuf, process::ExitCode};
+
+use clap::{CommandFactory, Parser};
 use crossterm::{
     execute,
     style::{Print, Stylize},
@@ -6,59 +8,382 @@ use crossterm::{
 
 use crate::{
     cli::{Cli, Commands},
+    config::{paint, paint_with, Entry, PathList, Settings},
+    logger, tmux,
     tui::{reset_terminal, start_tui},
 };
 
-pub fn run() -> Result<(), anyhow::Error> {
-    let colors = crate::config::init_colors();
-    let mut pathlist = crate::config::get_paths()?;
+/// Exit code when the picker is closed without opening anything, the same
+/// one shells use for Ctrl-C
+const CANCELLED: u8 = 130;
+
+pub fn run() -> Result<ExitCode, anyhow::Error> {
     let cli = Cli::parse();
+    let log_file = cli.log.clone().or_else(|| {
+        std::env::var_os(logger::LOG_ENV)
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+    });
+    logger::init(log_file.as_deref())?;
+    log::info!("tmux-fzy {} started", env!("CARGO_PKG_VERSION"));
+    if cli.no_color {
+        crate::config::disable_colors();
+    }
+    let mut settings = crate::config::init_settings();
+    settings.zoxide |= cli.zoxide;
+    if let Some(max_nodes) = cli.max_nodes {
+        settings.max_nodes = max_nodes;
+    }
+    if let Some(max_time) = cli.max_time {
+        settings.max_time = max_time;
+    }
+    // a socket passed on the command line replaces both configured ones
+    if cli.socket_name.is_some() || cli.socket_path.is_some() {
+        settings.socket_name = cli.socket_name;
+        settings.socket_path = cli.socket_path;
+    }
+    let socket = settings
+        .socket_path
+        .clone()
+        .map(tmux::Socket::Path)
+        .or_else(|| settings.socket_name.clone().map(tmux::Socket::Name));
+    tmux::configure(tmux::Options {
+        socket,
+        dry_run: cli.dry_run,
+    });
 
+    // only the commands that need the paths file read it, which creates it
+    // when it's missing
     match cli.command {
         None => {
-            if let Err(err) = start_tui(pathlist, colors) {
-                reset_terminal()?;
-                execute!(std::io::stderr(), Print("Error: ".red()))?;
-                for cause in err.chain() {
-                    execute!(std::io::stderr(), Print(cause), Print("\n"))?;
-                }
-            } else {
-                reset_terminal()?;
+            let pathlist = crate::config::get_paths()?;
+            if pathlist.entries.is_empty() && !settings.zoxide {
+                execute!(
+                    std::io::stderr(),
+                    Print("No directories configured. Run `tmux-fzy add <dir>`.\n")
+                )?;
+                return Ok(ExitCode::FAILURE);
             }
-        }
-
-        Some(Commands::List) => {
-            for (i, entry) in pathlist.entries.iter().enumerate() {
-                let i = format!("{}:", i);
+            // fail before entering the alternate screen if tmux is missing
+            if !cli.print {
+                tmux::status()?;
+            }
+            let result = start_tui(pathlist, settings, cli.print);
+            // the terminal has to be restored before anything is printed
+            reset_terminal()?;
+            print_planned()?;
+            let outcome = result?;
+            for warning in outcome.warnings {
+                log::warn!("{}", warning);
                 execute!(
-                    std::io::stdout(),
-                    Print(i.blue()),
-                    Print(entry.path.to_string_lossy()),
-                    Print(", min_depth: ".green()),
-                    Print(entry.min_depth),
-                    Print(", max_depth: ".green()),
-                    Print(entry.max_depth),
+                    std::io::stderr(),
+                    Print(paint("Warning: ".yellow())),
+                    Print(warning),
                     Print("\n")
                 )?;
             }
+            if !outcome.launched {
+                return Ok(ExitCode::from(CANCELLED));
+            }
+            let mut stdout = std::io::stdout();
+            for dir in outcome.chosen {
+                // written as is, paths don't have to be valid utf8
+                stdout
+                    .write_all(dir.as_os_str().as_encoded_bytes())
+                    .and_then(|_| stdout.write_all(b"\n"))
+                    .map_err(|e| anyhow::anyhow!(e))?;
+            }
+        }
+
+        Some(Commands::List { json: true }) => {
+            let json = crate::config::get_paths()?.to_json()?;
+            execute!(std::io::stdout(), Print(json), Print("\n"))?;
         }
 
+        Some(Commands::List { json: false }) => print_list(
+            &mut std::io::stdout(),
+            &crate::config::get_paths()?,
+            crate::config::colors_enabled(),
+        )?,
+
         Some(Commands::Add {
             maxdepth,
             mindepth,
+            depth,
+            follow_links,
+            show_hidden,
+            command,
+            env,
+            panes,
+            layout,
+            session_name,
+            target_window,
+            include_files,
+            file_command,
             paths,
         }) => {
+            let mut pathlist = crate::config::get_paths()?;
+            let (mindepth, maxdepth) = resolve_depth(depth, mindepth, maxdepth, &settings)?;
+            let mut full_paths = Vec::new();
             for path in paths {
-                let full_path = path.canonicalize()?;
-                pathlist.insert_row(full_path, mindepth, maxdepth)
+                let full_path = path
+                    .canonicalize()
+                    .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+                if !full_path.is_dir() {
+                    return Err(anyhow::anyhow!(
+                        "{} is not a directory",
+                        full_path.display()
+                    ));
+                }
+                full_paths.push(full_path);
+            }
+
+            for full_path in full_paths {
+                let inserted = pathlist.insert_row(Entry {
+                    path: full_path.clone(),
+                    min_depth: mindepth,
+                    max_depth: maxdepth,
+                    follow_links,
+                    show_hidden,
+                    command: command.clone(),
+                    env: env.iter().cloned().collect(),
+                    include_files: include_files || file_command.is_some(),
+                    file_command: file_command.clone(),
+                    panes,
+                    layout: layout.clone(),
+                    session_name: session_name.clone(),
+                    target_window: target_window.clone(),
+                    ..Default::default()
+                });
+                if !inserted {
+                    execute!(
+                        std::io::stdout(),
+                        Print(full_path.to_string_lossy()),
+                        Print(paint(" is already added, skipping".yellow())),
+                        Print("\n")
+                    )?;
+                }
             }
             pathlist.save_configuration()?;
         }
 
-        Some(Commands::Del { paths }) => {
+        Some(Commands::Del { index, paths }) => {
+            let mut pathlist = crate::config::get_paths()?;
+            pathlist.remove_indices(index)?;
             pathlist.remove_paths(paths)?;
             pathlist.save_configuration()?;
         }
+
+        Some(Commands::Move { from, to }) => {
+            let mut pathlist = crate::config::get_paths()?;
+            pathlist.move_row(from, to)?;
+            pathlist.save_configuration()?;
+        }
+
+        Some(Commands::Clear { yes }) => {
+            let mut pathlist = crate::config::get_paths()?;
+            let count = pathlist.entries.len();
+            if count == 0 {
+                execute!(std::io::stdout(), Print("No paths to remove\n"))?;
+                return Ok(ExitCode::SUCCESS);
+            }
+            if !yes && !confirm(&format!("Remove all {} paths?", count))? {
+                return Ok(ExitCode::SUCCESS);
+            }
+            pathlist.entries.clear();
+            pathlist.save_configuration()?;
+            execute!(
+                std::io::stdout(),
+                Print(format!("Removed {} paths\n", count))
+            )?;
+        }
+
+        Some(Commands::KillAll) => {
+            // killing the attached session would detach us midway
+            let current = if tmux::env() {
+                tmux::current_session()?
+            } else {
+                None
+            };
+            let mut killed = 0;
+            let mut failed = Vec::new();
+            for session in tmux::list_sessions()? {
+                if current.as_ref() == Some(&session) {
+                    continue;
+                }
+                match tmux::kill_session(&session) {
+                    Ok(true) => killed += 1,
+                    _ => failed.push(session),
+                }
+            }
+
+            print_planned()?;
+            execute!(
+                std::io::stdout(),
+                Print(format!("Killed {} sessions\n", killed))
+            )?;
+            if !failed.is_empty() {
+                execute!(
+                    std::io::stderr(),
+                    Print(paint("Failed to kill: ".red())),
+                    Print(failed.join(", ")),
+                    Print("\n")
+                )?;
+            }
+        }
+
+        // editing has to work even when the paths file doesn't parse anymore
+        Some(Commands::Edit) => crate::config::edit_paths()?,
+
+        Some(Commands::Paths) => {
+            print_location("paths: ", crate::config::paths_file())?;
+            print_location("config:", crate::config::config_file())?;
+        }
+
+        Some(Commands::Completions { shell }) => {
+            clap_complete::generate(
+                shell,
+                &mut Cli::command(),
+                "tmux-fzy",
+                &mut std::io::stdout(),
+            );
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Prints the commands `--dry-run` skipped, only once the picker has given
+/// the terminal back
+fn print_planned() -> Result<(), anyhow::Error> {
+    for command in tmux::take_planned() {
+        execute!(std::io::stderr(), Print(command), Print("\n"))?;
+    }
+    Ok(())
+}
+
+/// One line per entry with its index and depths, as `list` prints them.
+/// Styled only when `colors` is set
+fn print_list(
+    out: &mut impl Write,
+    pathlist: &PathList,
+    colors: bool,
+) -> Result<(), anyhow::Error> {
+    for (i, entry) in pathlist.entries.iter().enumerate() {
+        let i = format!("{}:", i);
+        execute!(
+            out,
+            Print(paint_with(i.blue(), colors)),
+            Print(entry.path.to_string_lossy()),
+            Print(paint_with(", min_depth: ".green(), colors)),
+            Print(entry.min_depth),
+            Print(paint_with(", max_depth: ".green(), colors)),
+            Print(entry.max_depth),
+            Print("\n")
+        )?;
     }
     Ok(())
 }
+
+/// Prints `label` followed by `path` and whether it exists
+fn print_location(label: &str, path: Option<PathBuf>) -> Result<(), anyhow::Error> {
+    let location = match path {
+        Some(path) if path.exists() => path.display().to_string(),
+        Some(path) => format!("{} (missing)", path.display()),
+        None => "unknown, neither XDG_CACHE_HOME nor HOME is set".to_string(),
+    };
+    execute!(
+        std::io::stdout(),
+        Print(paint(label.blue())),
+        Print(" "),
+        Print(location),
+        Print("\n")
+    )?;
+    Ok(())
+}
+
+/// Asks a yes/no question on stdin, anything but `y`/`yes` is a no
+fn confirm(question: &str) -> Result<bool, anyhow::Error> {
+    execute!(std::io::stdout(), Print(question), Print(" [y/N] "))?;
+    let mut answer = String::new();
+    std::io::stdin()
+        .read_line(&mut answer)
+        .map_err(|e| anyhow::anyhow!(e))?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// `--depth` sets both bounds, an explicit `--mindepth`/`--maxdepth` has to
+/// agree with it. Bounds that aren't given at all fall back to the defaults
+/// from the config
+fn resolve_depth(
+    depth: Option<usize>,
+    mindepth: Option<usize>,
+    maxdepth: Option<usize>,
+    settings: &Settings,
+) -> Result<(usize, usize), anyhow::Error> {
+    if let Some(depth) = depth {
+        if mindepth.is_some_and(|min| min != depth) || maxdepth.is_some_and(|max| max != depth) {
+            return Err(anyhow::anyhow!(
+                "--depth {} conflicts with --mindepth/--maxdepth",
+                depth
+            ));
+        }
+    }
+    let min = mindepth.or(depth).unwrap_or(settings.default_min_depth);
+    let max = maxdepth.or(depth).unwrap_or(settings.default_max_depth);
+    if min > max {
+        return Err(anyhow::anyhow!(
+            "min depth {} is greater than max depth {}",
+            min,
+            max
+        ));
+    }
+    Ok((min, max))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn list_without_colors() {
+        let pathlist = PathList {
+            entries: vec![
+                Entry {
+                    path: PathBuf::from("/srv/work"),
+                    min_depth: 1,
+                    max_depth: 2,
+                    ..Default::default()
+                },
+                Entry {
+                    path: PathBuf::from("/srv/misc"),
+                    ..Default::default()
+                },
+            ],
+        };
+
+        let mut out = Vec::new();
+        print_list(&mut out, &pathlist, false).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "0:/srv/work, min_depth: 1, max_depth: 2\n1:/srv/misc, min_depth: 0, max_depth: 0\n"
+        );
+    }
+
+    #[test]
+    fn depth() {
+        let settings = Settings::default();
+        assert_eq!(resolve_depth(None, None, None, &settings).unwrap(), (0, 0));
+        assert_eq!(
+            resolve_depth(Some(2), None, None, &settings).unwrap(),
+            (2, 2)
+        );
+        assert_eq!(
+            resolve_depth(None, Some(1), Some(3), &settings).unwrap(),
+            (1, 3)
+        );
+        assert!(resolve_depth(Some(2), Some(1), None, &settings).is_err());
+        // the default max depth is 0
+        assert!(resolve_depth(None, Some(2), None, &settings).is_err());
+        assert!(resolve_depth(None, Some(3), Some(1), &settings).is_err());
+    }
+}
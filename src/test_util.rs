@@ -0,0 +1,39 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::tmux::{self, Options, Socket};
+
+/// An empty directory for one test, named after it so parallel tests don't
+/// share one
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("tmux-fzy-{}-{}", name, std::process::id()));
+    _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Creates every directory in `dirs` under `root`
+pub fn make_dirs(root: &Path, dirs: &[&str]) {
+    for dir in dirs {
+        fs::create_dir_all(root.join(dir)).unwrap();
+    }
+}
+
+/// Points every tmux command at a server of our own so tests never touch the
+/// developer's sessions, false when tmux isn't installed
+pub fn tmux_server() -> bool {
+    tmux::configure(Options {
+        socket: Some(Socket::Name(format!(
+            "tmux-fzy-test-{}",
+            std::process::id()
+        ))),
+        dry_run: false,
+    });
+    Command::new("tmux")
+        .arg("-V")
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
@@ -1,18 +1,109 @@
 #![allow(dead_code)]
 
 use anyhow::Error;
-use std::process::{Command, Output, Stdio};
+use std::{
+    ffi::OsStr,
+    fmt,
+    io::{self, Read},
+    mem,
+    path::{Path, PathBuf},
+    process::{Child, Command, ExitStatus, Output, Stdio},
+    sync::{Mutex, OnceLock},
+    thread,
+    time::{Duration, Instant},
+};
 
-/// Check if tmux is running
+/// How long a tmux command may take before it is killed, a stuck server
+/// would otherwise freeze the picker
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A tmux server other than the default one, passed to every command
+pub enum Socket {
+    /// `-L`, a socket named this in tmux's socket directory
+    Name(String),
+    /// `-S`, a full path to the socket
+    Path(PathBuf),
+}
+
+/// How every command is run, set once at startup
+#[derive(Default)]
+pub struct Options {
+    /// the server to talk to, without one tmux finds it on its own through
+    /// `TMUX` and `TMUX_TMPDIR`
+    pub socket: Option<Socket>,
+    /// commands other than `QUERIES` are collected instead of run, see
+    /// `take_planned`
+    pub dry_run: bool,
+}
+
+static OPTIONS: OnceLock<Options> = OnceLock::new();
+
+/// Commands skipped by `dry_run`, in the order they were made
+static PLANNED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Commands that only read from the server, they run even with `dry_run`
+/// since what is done next depends on their answers
+const QUERIES: &[&str] = &["has-session", "list-sessions", "ls", "display-message"];
+
+/// Applies to every command made afterwards
+pub fn configure(options: Options) {
+    _ = OPTIONS.set(options);
+}
+
+/// The commands `dry_run` skipped since the last call, as `tmux <args>` lines
+pub fn take_planned() -> Vec<String> {
+    PLANNED
+        .lock()
+        .map(|mut planned| mem::take(&mut *planned))
+        .unwrap_or_default()
+}
+
+#[derive(Debug)]
+pub enum TmuxError {
+    /// the tmux binary is not on `PATH`
+    NotFound,
+    /// the command was killed after running for `COMMAND_TIMEOUT`
+    TimedOut,
+}
+
+impl fmt::Display for TmuxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TmuxError::NotFound => {
+                write!(f, "tmux was not found in PATH, install it to use tmux-fzy")
+            }
+            TmuxError::TimedOut => write!(
+                f,
+                "tmux didn't respond within {}s, the server may be stuck",
+                COMMAND_TIMEOUT.as_secs()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TmuxError {}
+
+/// Check if the tmux server is running
 pub fn status() -> Result<bool, Error> {
-    let status = Command::new("pgrep")
-        .arg("tmux")
-        .output()
-        .map_err(|e| anyhow::anyhow!(e))?
-        .status
-        .success();
+    let output = CommandBuilder::new().arg("list-sessions").run_output()?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
 
-    Ok(status)
+    server_running(output.status.success(), &stderr)
+}
+
+/// Maps the result of `tmux list-sessions` to whether a server is running,
+/// tmux reports a missing server on stderr rather than with a distinct code
+fn server_running(success: bool, stderr: &str) -> Result<bool, Error> {
+    if success {
+        return Ok(true);
+    }
+    if stderr.contains("no server running") || stderr.contains("error connecting to") {
+        return Ok(false);
+    }
+    Err(anyhow::anyhow!(
+        "Failed to query the tmux server: {}",
+        stderr.trim()
+    ))
 }
 
 /// Check if the 'TMUX' env variable is set
@@ -20,39 +111,68 @@ pub fn env() -> bool {
     std::env::var("TMUX").is_ok()
 }
 
+/// Target for exactly the session named `session_name`, tmux would
+/// otherwise fall back to the first session starting with it
+fn exact(session_name: &str) -> String {
+    format!("={}", session_name)
+}
+
 pub fn has_session(session_name: &str) -> Result<bool, Error> {
+    let target = exact(session_name);
     let status = CommandBuilder::new()
-        .args(vec!["has-session", "-t", session_name])
+        .args(vec!["has-session", "-t", &target])
         .run()?;
 
     Ok(status)
 }
 
-pub fn kill_session(session_name: &str) -> Result<(), Error> {
-    CommandBuilder::new()
-        .args(vec!["kill-session", "-t", session_name])
+pub fn kill_session(session_name: &str) -> Result<bool, Error> {
+    let target = exact(session_name);
+    let status = CommandBuilder::new()
+        .args(vec!["kill-session", "-t", &target])
         .run()?;
 
-    Ok(())
+    Ok(status)
+}
+
+/// Name of the session we are running in, only meaningful inside tmux. The
+/// pane from `TMUX_PANE` is used as target since without one tmux picks the
+/// most recently used session
+pub fn current_session() -> Result<Option<String>, Error> {
+    let pane = std::env::var("TMUX_PANE").unwrap_or_default();
+    let mut builder = CommandBuilder::new().args(vec!["display-message", "-p"]);
+    if !pane.is_empty() {
+        builder = builder.args(vec!["-t", &pane]);
+    }
+    let output = builder.arg("#{session_name}").run_capture_output()?;
+
+    Ok(parse_current_session(&output))
+}
+
+/// The session `display-message` printed, `None` for empty output like
+/// outside of a client
+fn parse_current_session(output: &str) -> Option<String> {
+    parse_sessions(output).into_iter().next()
 }
 
-/// lists all active sessions
+/// lists all active sessions, empty if there is no server
 pub fn list_sessions() -> Result<Vec<String>, Error> {
     let output = CommandBuilder::new()
-        .args(vec!["ls", "-F", "'#{session_name}'"])
+        .args(vec!["ls", "-F", "#{session_name}"])
         .run_capture_output()?;
 
-    let sessions = output
-        .lines()
-        .map(|line| {
-            let session = line.trim();
-            let prefix = session.strip_prefix('\'').unwrap();
-            let suffix = prefix.strip_suffix('\'').unwrap();
-            suffix.to_string()
-        })
-        .collect::<Vec<String>>();
+    Ok(parse_sessions(&output))
+}
 
-    Ok(sessions)
+/// One session name per line, quotes around a name are stripped in case the
+/// format was quoted
+fn parse_sessions(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .map(|line| line.trim().trim_matches('\''))
+        .filter(|session| !session.is_empty())
+        .map(|session| session.to_string())
+        .collect()
 }
 
 /// Detach from the current session and start a new session, useful when
@@ -65,6 +185,16 @@ pub fn switch_client(session_name: &str) -> Result<(), Error> {
     Ok(())
 }
 
+/// Switch the current client back to the last used session, false if there
+/// is none
+pub fn switch_last() -> Result<bool, Error> {
+    let status = CommandBuilder::new()
+        .args(vec!["switch-client", "-l"])
+        .run()?;
+
+    Ok(status)
+}
+
 /// attach to a new session, useful when you are outside a tmux session
 pub fn attach(session_name: &str) -> Result<(), Error> {
     CommandBuilder::new()
@@ -74,59 +204,145 @@ pub fn attach(session_name: &str) -> Result<(), Error> {
     Ok(())
 }
 
-pub fn new_session(session_name: &str, path: &str) -> Result<(), Error> {
+pub fn new_session(session_name: &str, path: &Path) -> Result<(), Error> {
     CommandBuilder::new()
-        .args(vec!["new-session", "-s", session_name, "-c", path])
+        .args(vec!["new-session", "-s", session_name, "-c"])
+        .path(path)
         .run_inherit_stdio()?;
 
     Ok(())
 }
 
 /// don't attach new session to current terminal
-pub fn new_session_detach(session_name: &str, path: &str) -> Result<(), Error> {
+pub fn new_session_detach(session_name: &str, path: &Path) -> Result<(), Error> {
     CommandBuilder::new()
-        .args(vec!["new-session", "-ds", session_name, "-c", path])
+        .args(vec!["new-session", "-ds", session_name, "-c"])
+        .path(path)
         .run_inherit_stdio()?;
 
     Ok(())
 }
 
+/// Types `keys` into the session followed by Enter
+pub fn send_keys(session_name: &str, keys: &str) -> Result<bool, Error> {
+    let status = CommandBuilder::new()
+        .args(vec!["send-keys", "-t", session_name, keys, "Enter"])
+        .run()?;
+
+    Ok(status)
+}
+
+/// Adds a pane in `path` to the session's current window, the active pane
+/// stays the same
+pub fn split_window(session_name: &str, path: &Path) -> Result<bool, Error> {
+    let status = CommandBuilder::new()
+        .args(vec!["split-window", "-d", "-t", session_name, "-c"])
+        .path(path)
+        .run()?;
+
+    Ok(status)
+}
+
+/// Makes `window` the session's current window, so attaching or switching to
+/// the session lands in it. False if there is no such window
+pub fn select_window(session_name: &str, window: &str) -> Result<bool, Error> {
+    let target = format!("{}:{}", session_name, window);
+    let status = CommandBuilder::new()
+        .args(vec!["select-window", "-t", &target])
+        .run()?;
+
+    Ok(status)
+}
+
+/// Adds a window named `window` in `path` to the session and makes it the
+/// current one
+pub fn new_window(session_name: &str, window: &str, path: &Path) -> Result<bool, Error> {
+    let target = format!("{}:", session_name);
+    let status = CommandBuilder::new()
+        .args(vec!["new-window", "-t", &target, "-n", window, "-c"])
+        .path(path)
+        .run()?;
+
+    Ok(status)
+}
+
+/// Arranges the panes of the session's current window, `layout` is a preset
+/// like `tiled` or a layout string as printed by `#{window_layout}`
+pub fn select_layout(session_name: &str, layout: &str) -> Result<bool, Error> {
+    let status = CommandBuilder::new()
+        .args(vec!["select-layout", "-t", session_name, layout])
+        .run()?;
+
+    Ok(status)
+}
+
+/// Sets `key` in the session's environment, it is inherited by windows and
+/// panes created afterwards
+pub fn set_environment(session_name: &str, key: &str, value: &str) -> Result<bool, Error> {
+    let status = CommandBuilder::new()
+        .args(vec!["set-environment", "-t", session_name, key, value])
+        .run()?;
+
+    Ok(status)
+}
+
+/// tmux doesn't allow `.` and `:` in session names since they separate the
+/// window and pane parts of a target, they are replaced with `_`
+pub fn sanitize_session_name(name: &str) -> String {
+    name.trim().replace(['.', ':'], "_")
+}
+
 pub struct CommandBuilder<'a> {
-    args: Vec<&'a str>,
+    args: Vec<&'a OsStr>,
+    /// index of the tmux command in `args`, after the socket arguments
+    command_start: usize,
 }
 
 impl<'a> CommandBuilder<'a> {
+    /// For the server picked with `configure`
     pub fn new() -> CommandBuilder<'a> {
-        CommandBuilder { args: Vec::new() }
+        Self::with_socket(OPTIONS.get().and_then(|options| options.socket.as_ref()))
+    }
+
+    /// Starts with the socket arguments, they have to come before the
+    /// command
+    fn with_socket(socket: Option<&'a Socket>) -> CommandBuilder<'a> {
+        let mut args = Vec::new();
+        match socket {
+            Some(Socket::Name(name)) => args.extend([OsStr::new("-L"), OsStr::new(name)]),
+            Some(Socket::Path(path)) => args.extend([OsStr::new("-S"), path.as_os_str()]),
+            None => {}
+        }
+        CommandBuilder {
+            command_start: args.len(),
+            args,
+        }
     }
 
     pub fn arg(mut self, s: &'a str) -> Self {
-        self.args.push(s);
+        self.args.push(OsStr::new(s));
         self
     }
 
     pub fn args(mut self, s: Vec<&'a str>) -> Self {
-        self.args.extend(s);
+        self.args.extend(s.into_iter().map(OsStr::new));
+        self
+    }
+
+    /// Paths are passed as is since they don't have to be valid utf8
+    pub fn path(mut self, path: &'a Path) -> Self {
+        self.args.push(path.as_os_str());
         self
     }
 
     pub fn run(self) -> Result<bool, Error> {
-        let command = Command::new("tmux")
-            .args(self.args)
-            .output()
-            .map_err(|err| anyhow::anyhow!(err))?
-            .status
-            .success();
+        let command = self.run_output()?.status.success();
 
         Ok(command)
     }
 
     pub fn run_capture_output(self) -> Result<String, Error> {
-        let command = Command::new("tmux")
-            .args(self.args)
-            .stdout(Stdio::piped())
-            .output()
-            .map_err(|err| anyhow::anyhow!(err))?;
+        let command = self.run_output()?;
 
         let stdout = String::from_utf8_lossy(&command.stdout);
         let output = stdout.to_string();
@@ -134,14 +350,263 @@ impl<'a> CommandBuilder<'a> {
         Ok(output)
     }
 
+    /// Captures stdout and stderr, killing tmux if it takes longer than
+    /// `COMMAND_TIMEOUT`
+    pub fn run_output(self) -> Result<Output, Error> {
+        if let Some(output) = self.dry_run() {
+            return Ok(output);
+        }
+        let output = Command::new("tmux")
+            .args(&self.args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(spawn_error)
+            .and_then(|child| wait_with_timeout(child, COMMAND_TIMEOUT));
+        log_command(&self.args, &output);
+        output
+    }
+
+    /// Not timed out since `attach` runs for as long as the session is attached
     pub fn run_inherit_stdio(self) -> Result<Output, Error> {
+        if let Some(output) = self.dry_run() {
+            return Ok(output);
+        }
         let command = Command::new("tmux")
-            .args(self.args)
+            .args(&self.args)
             .stdin(Stdio::inherit())
             .stdout(Stdio::inherit())
             .stderr(Stdio::inherit())
             .output()
-            .map_err(|err| anyhow::anyhow!(err))?;
-        Ok(command)
+            .map_err(spawn_error);
+        log_command(&self.args, &command);
+        command
+    }
+}
+
+impl CommandBuilder<'_> {
+    /// With `dry_run` a command other than the `QUERIES` is recorded and
+    /// answered with an empty success instead of being run
+    fn dry_run(&self) -> Option<Output> {
+        if !OPTIONS.get().is_some_and(|options| options.dry_run) {
+            return None;
+        }
+        let command = self.args.get(self.command_start)?;
+        if QUERIES.iter().any(|query| command == query) {
+            return None;
+        }
+        let line = format!("tmux {}", command_line(&self.args));
+        log::info!("dry run: {}", line);
+        if let Ok(mut planned) = PLANNED.lock() {
+            planned.push(line);
+        }
+        Some(Output {
+            status: ExitStatus::default(),
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+        })
+    }
+}
+
+/// `args` joined by spaces, single quoted where a shell would split or expand
+/// them so the line can be pasted
+fn command_line(args: &[&OsStr]) -> String {
+    args.iter()
+        .map(|arg| {
+            let arg = arg.to_string_lossy();
+            let plain = !arg.is_empty()
+                && arg
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_./:=@%+,".contains(c));
+            if plain {
+                arg.into_owned()
+            } else {
+                format!("'{}'", arg.replace('\'', r"'\''"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Logs the invocation along with how it ended, the arguments are only joined
+/// when logging is on
+fn log_command(args: &[&OsStr], output: &Result<Output, Error>) {
+    let args = || command_line(args);
+    match output {
+        Ok(output) if output.status.success() => {
+            log::debug!("tmux {}: {}", args(), output.status)
+        }
+        Ok(output) => log::warn!(
+            "tmux {}: {}, {}",
+            args(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ),
+        Err(err) => log::error!("tmux {}: {}", args(), err),
+    }
+}
+
+/// Like `Child::wait_with_output` but gives up after `timeout`, killing the
+/// child. The pipes are drained on their own threads so a chatty child can't
+/// block on a full pipe while we wait
+fn wait_with_timeout(mut child: Child, timeout: Duration) -> Result<Output, Error> {
+    let drain = |pipe: Option<Box<dyn Read + Send>>| {
+        thread::spawn(move || {
+            let mut buf = Vec::new();
+            if let Some(mut pipe) = pipe {
+                _ = pipe.read_to_end(&mut buf);
+            }
+            buf
+        })
+    };
+    let stdout = drain(child.stdout.take().map(|pipe| Box::new(pipe) as _));
+    let stderr = drain(child.stderr.take().map(|pipe| Box::new(pipe) as _));
+
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(|e| anyhow::anyhow!(e))? {
+            break status;
+        }
+        if started.elapsed() >= timeout {
+            _ = child.kill();
+            _ = child.wait();
+            return Err(Error::new(TmuxError::TimedOut));
+        }
+        thread::sleep(Duration::from_millis(5));
+    };
+
+    Ok(Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
+
+fn spawn_error(err: io::Error) -> Error {
+    if err.kind() == io::ErrorKind::NotFound {
+        Error::new(TmuxError::NotFound)
+    } else {
+        anyhow::anyhow!(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::tmux_server;
+
+    #[test]
+    fn sessions() {
+        assert_eq!(parse_sessions("main\nwork\n"), ["main", "work"]);
+        assert_eq!(parse_sessions("'main'\n'my work'\n"), ["main", "my work"]);
+        assert_eq!(parse_sessions("main\n\n  \nwork"), ["main", "work"]);
+        assert!(parse_sessions("").is_empty());
+        assert!(parse_sessions("\n''\n").is_empty());
+    }
+
+    #[test]
+    fn current() {
+        assert_eq!(parse_current_session("main\n").as_deref(), Some("main"));
+        assert_eq!(
+            parse_current_session("'my work'\n").as_deref(),
+            Some("my work")
+        );
+        assert_eq!(parse_current_session("\nmain\n").as_deref(), Some("main"));
+        assert_eq!(parse_current_session(""), None);
+        assert_eq!(parse_current_session("\n"), None);
+    }
+
+    #[test]
+    fn server() {
+        assert!(server_running(true, "").unwrap());
+        assert!(!server_running(false, "no server running on /tmp/tmux-0/default\n").unwrap());
+        assert!(!server_running(
+            false,
+            "error connecting to /tmp/tmux-0/default (No such file or directory)\n"
+        )
+        .unwrap());
+        let err = server_running(false, "lost server\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Failed to query the tmux server: lost server"
+        );
+    }
+
+    fn spawn(script: &str) -> Child {
+        Command::new("sh")
+            .args(["-c", script])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap()
+    }
+
+    #[test]
+    fn slow_command_times_out() {
+        let started = Instant::now();
+        let err = wait_with_timeout(spawn("sleep 10"), Duration::from_millis(100)).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<TmuxError>(),
+            Some(TmuxError::TimedOut)
+        ));
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let output =
+            wait_with_timeout(spawn("echo out; echo err >&2"), Duration::from_secs(5)).unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"out\n");
+        assert_eq!(output.stderr, b"err\n");
+
+        // more than a pipe buffer, it would block without being drained
+        let output =
+            wait_with_timeout(spawn("head -c 1000000 /dev/zero"), Duration::from_secs(5)).unwrap();
+        assert_eq!(output.stdout.len(), 1_000_000);
+    }
+
+    #[test]
+    fn socket_comes_before_the_command() {
+        let args = |builder: CommandBuilder| {
+            let command = builder.args[builder.command_start].to_owned();
+            (command_line(&builder.args), command)
+        };
+
+        let name = Socket::Name("work".to_string());
+        let builder = CommandBuilder::with_socket(Some(&name)).args(vec!["new-session", "-d"]);
+        assert_eq!(
+            args(builder),
+            ("-L work new-session -d".to_string(), "new-session".into())
+        );
+
+        let path = Socket::Path(PathBuf::from("/tmp/my socket"));
+        let builder = CommandBuilder::with_socket(Some(&path))
+            .arg("attach")
+            .args(vec!["-t", "main"]);
+        assert_eq!(
+            args(builder),
+            (
+                "-S '/tmp/my socket' attach -t main".to_string(),
+                "attach".into()
+            )
+        );
+
+        let builder = CommandBuilder::with_socket(None).arg("ls");
+        assert_eq!(args(builder), ("ls".to_string(), "ls".into()));
+    }
+
+    #[test]
+    fn sessions_are_matched_exactly() {
+        if !tmux_server() {
+            return;
+        }
+        let dir = std::env::temp_dir();
+        new_session_detach("exact-api-gateway", &dir).unwrap();
+
+        assert!(has_session("exact-api-gateway").unwrap());
+        assert!(!has_session("exact-api").unwrap());
+        assert!(!kill_session("exact-api").unwrap());
+        assert!(has_session("exact-api-gateway").unwrap());
+        assert!(kill_session("exact-api-gateway").unwrap());
+        assert!(!has_session("exact-api-gateway").unwrap());
     }
 }
@@ -1,49 +1,160 @@
-use std::{cell::Cell, collections::BinaryHeap, path::PathBuf, sync::mpsc, time::Duration};
+use std::{
+    cell::Cell,
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+    ffi::OsStr,
+    fs::OpenOptions,
+    io::{IsTerminal, Write},
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver, Sender, TryRecvError},
+        Arc, OnceLock,
+    },
+    time::{Duration, Instant, SystemTime},
+};
 
 use crossterm::{
     event::{KeyCode, KeyEvent, KeyModifiers},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use fuzzy_matcher::FuzzyMatcher;
 use ratatui::{
     backend::CrosstermBackend,
-    layout::{Constraint, Direction, Layout},
-    widgets::ListState,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier},
+    widgets::{Clear, ListState},
     Frame, Terminal,
 };
-use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use rayon::{
+    iter::{IntoParallelRefIterator, ParallelIterator},
+    slice::ParallelSliceMut,
+};
 use walkdir::WalkDir;
 
 use crate::{
-    config::{Colors, PathList},
+    config::{self, BorderStyle, Colors, Entry, PathList, Settings},
+    frecency::{self, Frecency},
+    glob,
+    matcher::{name_bonus, Matcher, MatcherKind},
+    pins::Pins,
     tmux,
-    tui_components::{get_input_bar, get_list, get_total_item_no},
+    tui_components::{
+        get_confirm_bar, get_help, get_input_bar, get_input_block, get_list, get_mode,
+        get_rename_bar, get_results_block, get_total_item_no, mode_label, prompt_width, Row, HELP,
+        HELP_WIDTH, RENAME_PROMPT,
+    },
+    zoxide,
 };
 
-pub struct PathItem<'a> {
-    pub path: &'a str,
-    pub fullpath: &'a str,
+pub struct PathItem {
+    pub path: Arc<str>,
+    /// `dir` converted lossily, used for matching and display
+    pub fullpath: Arc<str>,
+    /// the directory itself, its name doesn't have to be valid utf8. A file
+    /// for entries with `include_files`
+    pub dir: Arc<Path>,
     pub score: i64,
+    /// positions of the matched chars in `path`, highlighted as drawn
     pub indices: Vec<usize>,
+    /// score used instead of the matcher's while the query is empty
+    pub frecency: i64,
+    /// a tmux session with the default name for this directory is running
+    pub running: bool,
+    /// listed above every item that isn't pinned
+    pub pinned: bool,
+    /// picked with Tab to create sessions for several directories at once
+    pub marked: bool,
+    pub source: Arc<Source>,
 }
 
 #[derive(Default)]
-struct StatefulList<'a> {
+struct StatefulList {
     state: ListState,
-    items: BinaryHeap<PathItem<'a>>,
-    history: Vec<BinaryHeap<PathItem<'a>>>,
+    /// sorted best match first
+    items: Vec<PathItem>,
+    /// query the current `items` were filtered with
+    query: String,
+    /// number of matches before they were capped to `max_results`
+    matched: usize,
+    history: Vec<Snapshot>,
+    wrap: bool,
+}
+
+/// A previous state of `StatefulList`
+struct Snapshot {
+    query: String,
+    items: Vec<PathItem>,
+    matched: usize,
 }
 
-struct App<'a> {
+struct App {
     running: bool,
     input: String,
     cursor_pos: usize,
     total_items: usize,
-    colors: Colors,
-    list: StatefulList<'a>,
+    settings: Settings,
+    list: StatefulList,
     spinner: Spinner,
     loaded: bool,
+    frecency: Frecency,
+    pins: Pins,
+    /// session name being typed in the rename prompt, `None` when it's closed
+    rename: Option<String>,
+    /// session waiting for the kill to be confirmed
+    confirm_kill: Option<String>,
+    /// the keybinding overlay is shown
+    help: bool,
+    /// a session was opened or switched to
+    launched: bool,
+    /// Enter picks directories to print instead of opening sessions
+    print: bool,
+    /// directories picked in `print` mode
+    chosen: Vec<PathBuf>,
+    paths: Arc<PathList>,
+    /// directories found by the background walk
+    walker: Walker,
+    /// added to every entry's max depth, changed with `+`/`-`
+    depth_offset: isize,
+    /// printed once the terminal is restored
+    warnings: Vec<String>,
+    /// number of visible result rows as of the last draw
+    viewport_rows: usize,
+    mode: Mode,
+    /// first key of a two key normal mode command like `dd`
+    pending_key: Option<char>,
+    /// shown in the status line for a few seconds
+    message: Option<(String, Instant)>,
+    /// names of the running tmux sessions as of the last refresh
+    sessions: HashSet<String>,
+    /// sessions being listed in the background, see `SESSIONS_INTERVAL`
+    sessions_fetch: Option<Receiver<Vec<String>>>,
+    sessions_fetched_at: Instant,
+    /// every directory received so far, a reload walks everything again
+    seen: HashMap<Arc<Path>, usize>,
+    /// modification time of the paths file as of the last (re)load
+    paths_modified: Option<SystemTime>,
+    /// when the query was last typed into, until the list has caught up
+    typed_at: Option<Instant>,
+    /// kept for as long as the picker runs, on Linux the copied text is only
+    /// available while its owner is alive
+    clipboard: Option<arboard::Clipboard>,
+}
+
+/// Vim like modes, keys type into the query in `Insert` and move around the
+/// list in `Normal`
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Insert,
+    Normal,
+}
+
+impl Mode {
+    pub fn label(self) -> &'static str {
+        match self {
+            Mode::Insert => "INSERT",
+            Mode::Normal => "NORMAL",
+        }
+    }
 }
 
 pub struct Spinner {
@@ -52,172 +163,563 @@ pub struct Spinner {
     pub chars: [&'static str; 10],
 }
 
-type Term = Terminal<CrosstermBackend<std::io::Stdout>>;
+type Term = Terminal<CrosstermBackend<Box<dyn Write>>>;
 
-pub fn start_tui(paths: PathList, colors: Colors) -> Result<(), anyhow::Error> {
-    let mut terminal = init_terminal()?;
-    let statefullist = StatefulList::default();
-    let mut app = App::new(statefullist, colors, 0);
+/// Where items were found, shared by all of them
+pub struct Source {
+    /// path of the entry the items were walked from, or `ZOXIDE_SOURCE`
+    pub name: String,
+    /// the entry's color for the items' names
+    pub color: Option<Color>,
+    /// the entry's `session_name` template
+    pub session_name: Option<String>,
+    /// components in the entry's path, a directory found under overlapping
+    /// entries belongs to the most specific one like in `PathList::entry_for`
+    pub specificity: usize,
+}
 
-    let (tx, rx) = mpsc::channel();
+/// `(dir, dir_name, source)` as sent by the walker, see `PathItem`
+type Found = (PathBuf, String, Arc<Source>);
 
-    let t1 = std::thread::spawn(move || {
-        let paths = expand_paths(paths);
-        _ = tx.send(paths);
-        drop(tx);
-    });
+/// Source of the directories that came from zoxide instead of an entry
+const ZOXIDE_SOURCE: &str = "zoxide";
 
-    let paths: Cell<Vec<(String, String)>> = Cell::new(vec![]);
+/// How long a status line message stays visible
+const MESSAGE_DURATION: Duration = Duration::from_secs(3);
 
-    while app.running {
-        let timeout = Duration::from_millis(16);
-        if crossterm::event::poll(timeout)? {
-            match crossterm::event::read()? {
-                crossterm::event::Event::Key(KeyEvent {
-                    code, modifiers, ..
-                }) => match (code, modifiers) {
-                    (KeyCode::Char(c), KeyModifiers::NONE) => {
-                        app.input.push(c);
-                        app.cursor_pos += 1;
-                        app.refresh();
-                    }
-                    (KeyCode::Char(c), KeyModifiers::SHIFT) => {
-                        app.input.push(c.to_ascii_uppercase());
-                        app.cursor_pos += 1;
-                        app.refresh();
-                    }
-                    (KeyCode::Backspace, KeyModifiers::NONE) => {
-                        _ = app.input.pop();
-                        app.cursor_pos = app.cursor_pos.saturating_sub(1);
-                        app.undo();
-                    }
-                    (KeyCode::Esc, KeyModifiers::NONE) => app.running = false,
-                    (KeyCode::Char('c'), KeyModifiers::CONTROL) => app.running = false,
+/// How long typing has to pause before the list is filtered again, so a
+/// burst of keys is matched once
+const TYPING_DELAY: Duration = Duration::from_millis(50);
 
-                    (KeyCode::Char('j'), KeyModifiers::CONTROL)
-                    | (KeyCode::Down, KeyModifiers::NONE) => app.list.next(),
+/// How often the paths file is checked for changes
+const RELOAD_INTERVAL: Duration = Duration::from_millis(200);
 
-                    (KeyCode::Char('k'), KeyModifiers::CONTROL)
-                    | (KeyCode::Up, KeyModifiers::NONE) => app.list.prev(),
+/// How often the running sessions are listed again to catch sessions created
+/// or killed outside the picker
+const SESSIONS_INTERVAL: Duration = Duration::from_secs(3);
 
-                    (KeyCode::Char('d'), KeyModifiers::CONTROL)
-                    | (KeyCode::Down, KeyModifiers::CONTROL) => app.list.scroll_next(),
+/// What happened in the picker, known once it is closed
+pub struct Outcome {
+    /// a session was opened or switched to, as opposed to quitting
+    pub launched: bool,
+    /// to show after the terminal is reset
+    pub warnings: Vec<String>,
+    /// directories picked in print mode, to write to stdout once the
+    /// terminal is reset
+    pub chosen: Vec<PathBuf>,
+}
 
-                    (KeyCode::Char('u'), KeyModifiers::CONTROL)
-                    | (KeyCode::Up, KeyModifiers::CONTROL) => app.list.scroll_prev(),
+/// Runs the picker until a session is opened or it is quit. With `print`
+/// Enter only picks directories, see `Outcome::chosen`
+pub fn start_tui(
+    paths: PathList,
+    settings: Settings,
+    print: bool,
+) -> Result<Outcome, anyhow::Error> {
+    // restore the terminal before the panic message is printed, a crash would
+    // otherwise leave it in raw mode on the alternate screen
+    let previous_hook = Arc::new(std::panic::take_hook());
+    let hook = Arc::clone(&previous_hook);
+    std::panic::set_hook(Box::new(move |info| {
+        _ = reset_terminal();
+        hook(info);
+    }));
 
-                    (KeyCode::Enter, KeyModifiers::NONE) => {
-                        if let Some(i) = app.list.state.selected() {
-                            if let Some(item) = app.list.items.iter().nth(i) {
-                                app.running = false;
-                                start_tmux(item.fullpath)?;
-                            } else {
-                                return Err(anyhow::anyhow!("Indexing Failed"));
-                            }
-                        }
-                    }
+    let outcome = run_picker(paths, settings, print);
 
-                    _ => {}
+    // put back whatever hook was set before, dropping ours releases it
+    drop(std::panic::take_hook());
+    if let Ok(previous_hook) = Arc::try_unwrap(previous_hook) {
+        std::panic::set_hook(previous_hook);
+    }
+    outcome
+}
+
+fn run_picker(
+    paths: PathList,
+    mut settings: Settings,
+    print: bool,
+) -> Result<Outcome, anyhow::Error> {
+    let mut terminal = init_terminal()?;
+    if !config::colors_enabled() {
+        settings.colors = Colors::plain();
+    }
+    let statefullist = StatefulList {
+        wrap: settings.wrap,
+        ..Default::default()
+    };
+    let mut app = App::new(statefullist, settings, 0, Arc::new(paths));
+    app.print = print;
+    if app.settings.restore_query {
+        if let Some(query) = config::load_last_query() {
+            app.cursor_pos = query.chars().count();
+            app.input = query;
+            app.refresh();
+        }
+    }
+    let mut last_reload_check = Instant::now();
+
+    while app.running {
+        let timeout = Duration::from_millis(16);
+        if crossterm::event::poll(timeout)? {
+            match crossterm::event::read()? {
+                crossterm::event::Event::Key(KeyEvent {
+                    code, modifiers, ..
+                }) if app.rename.is_some() => app.rename_key(code, modifiers)?,
+                crossterm::event::Event::Key(KeyEvent {
+                    code, modifiers, ..
+                }) if app.confirm_kill.is_some() => app.confirm_kill_key(code, modifiers)?,
+                // any key closes the help
+                crossterm::event::Event::Key(_) if app.help => app.help = false,
+                crossterm::event::Event::Key(KeyEvent {
+                    code, modifiers, ..
+                }) => match app.mode {
+                    Mode::Insert => app.insert_key(code, modifiers)?,
+                    Mode::Normal => app.normal_key(code, modifiers)?,
                 },
                 crossterm::event::Event::Resize(_, _) => terminal.autoresize()?,
                 _ => {}
             }
         }
+        if app
+            .typed_at
+            .is_some_and(|typed_at| typed_at.elapsed() >= TYPING_DELAY)
+        {
+            app.sync_query();
+        }
         terminal.draw(|f| render_frame(f, &mut app))?;
+        if last_reload_check.elapsed() >= RELOAD_INTERVAL {
+            last_reload_check = Instant::now();
+            app.reload_paths();
+        }
         if !app.loaded {
-            if let Ok(rx_paths) = rx.try_recv() {
-                paths.replace(rx_paths);
-                unsafe {
-                    app.list = StatefulList::from(&*paths.as_ptr());
-                    app.total_items = app.list.items.len();
-                };
-                app.spinner.visible = false;
-            }
+            app.receive_paths();
         }
+        app.poll_sessions();
     }
 
-    t1.join().unwrap();
-
-    Ok(())
+    if app.settings.restore_query {
+        _ = config::save_last_query(&app.input);
+    }
+    Ok(Outcome {
+        launched: app.launched,
+        warnings: app.warnings,
+        chosen: app.chosen,
+    })
 }
 
 fn render_frame(f: &mut Frame<'_>, app: &mut App) {
+    let input_block = get_input_block(app.settings.border_style, &app.settings.colors);
+    // borders take a row above and below the query
+    let input_height = match app.settings.border_style {
+        BorderStyle::None => 1,
+        BorderStyle::Plain | BorderStyle::Rounded => 3,
+    };
+    // the query goes below the list when it is drawn bottom up, with the
+    // status line between them either way
+    let bottom_up = app.settings.bottom_up;
+    let prompt = Constraint::Length(input_height + 1);
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .margin(1)
-        .constraints([Constraint::Min(2), Constraint::Percentage(100)].as_ref())
+        .constraints(if bottom_up {
+            [Constraint::Min(0), prompt]
+        } else {
+            [prompt, Constraint::Min(0)]
+        })
         .split(f.size());
+    let (prompt_area, list_area) = if bottom_up {
+        (chunks[1], chunks[0])
+    } else {
+        (chunks[0], chunks[1])
+    };
 
-    let top = Layout::default()
+    let input = Constraint::Length(input_height);
+    let status = Constraint::Length(1);
+    let prompt_rows = Layout::default()
         .direction(Direction::Vertical)
-        .constraints([Constraint::Min(1), Constraint::Min(1)].as_ref())
-        .split(chunks[0]);
+        .constraints(if bottom_up {
+            [status, input]
+        } else {
+            [input, status]
+        })
+        .split(prompt_area);
+    let (input_outer, status_area) = if bottom_up {
+        (prompt_rows[1], prompt_rows[0])
+    } else {
+        (prompt_rows[0], prompt_rows[1])
+    };
+    let input_area = input_block.inner(input_outer);
 
-    let rows = chunks[1].height;
+    let rows = list_area.height;
+    let results_block = get_results_block(
+        &app.settings.results_title,
+        app.settings.border_style,
+        &app.settings.colors,
+    );
+    app.viewport_rows = results_block.inner(list_area).height as usize;
     let curr_row = app.list.state.selected();
+    let list_rows = app.list.rows(app.settings.group_by_source, bottom_up);
+    // headers shift the items down, the selection is kept as an item index
+    let selected_row = curr_row.and_then(|i| {
+        list_rows
+            .iter()
+            .enumerate()
+            .filter(|(_, row)| matches!(row, Row::Item(_)))
+            .nth(i)
+            .map(|(row, _)| row)
+    });
+    let offset = scroll_offset(
+        app.list.state.offset(),
+        selected_row,
+        list_rows.len(),
+        app.viewport_rows,
+        app.settings.scroll_margin,
+    );
+    let mut list_state = ListState::default()
+        .with_offset(offset)
+        .with_selected(selected_row);
+
+    let (input_bar, cursor_x) = match (&app.rename, &app.confirm_kill) {
+        (Some(name), _) => (
+            get_rename_bar(name, &app.settings.colors),
+            RENAME_PROMPT.len() + name.chars().count(),
+        ),
+        (None, Some(session_name)) => {
+            let question = format!("Kill session '{}'? (y/n)", session_name);
+            let width = question.chars().count();
+            (get_confirm_bar(question, &app.settings.colors), width)
+        }
+        (None, None) => (
+            get_input_bar(&app.input, &app.settings.prompt, &app.settings.colors),
+            prompt_width(&app.settings.prompt) + app.cursor_pos,
+        ),
+    };
+    let list_width = results_block.inner(list_area).width;
+    let items = get_list(
+        &list_rows,
+        rows,
+        list_width,
+        selected_row,
+        results_block,
+        bottom_up,
+        &app.settings.colors,
+    );
+    let selected = curr_row
+        .and_then(|i| app.list.items.get(i))
+        .map(|item| &*item.fullpath);
+    let message = app
+        .message
+        .as_ref()
+        .filter(|(_, shown_at)| shown_at.elapsed() < MESSAGE_DURATION)
+        .map(|(message, _)| message.as_str());
+    let mode_label = mode_label(app.mode, app.depth_offset);
+    let mode_width = mode_label.chars().count() as u16 + 1;
+    let mode = get_mode(mode_label, &app.settings.colors);
+    let status_row = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Length(mode_width), Constraint::Min(1)].as_ref())
+        .split(status_area);
+    let mut notes = Vec::new();
+    if app.list.matched > app.list.items.len() {
+        notes.push("capped");
+    }
+    let errors;
+    if let Some(walked) = app.walker.walked.get() {
+        if walked.truncated {
+            notes.push("truncated");
+        }
+        if walked.errors > 0 {
+            errors = format!("{} unreadable", walked.errors);
+            notes.push(&errors);
+        }
+    }
+    let status = get_total_item_no(
+        app.total_items,
+        app.list.items.len(),
+        &notes,
+        message.or(selected),
+        status_row[1].width,
+        &app.settings.colors,
+        &mut app.spinner,
+    );
+
+    f.render_widget(input_block, input_outer);
+    f.render_widget(input_bar, input_area);
+    f.render_widget(mode, status_row[0]);
+    f.render_widget(status, status_row[1]);
+    f.render_stateful_widget(items, list_area, &mut list_state);
+    *app.list.state.offset_mut() = list_state.offset();
+
+    f.set_cursor(input_area.x + cursor_x as u16, input_area.y);
 
-    let input_bar = get_input_bar(&app.input, &app.colors);
-    let items = get_list(&app.list.items, rows, curr_row, &app.colors);
-    let status = get_total_item_no(app.total_items, items.len(), &app.colors, &mut app.spinner);
+    if app.help {
+        let area = centered(f.size(), HELP_WIDTH, HELP.len() as u16 + 2);
+        f.render_widget(Clear, area);
+        f.render_widget(get_help(&app.settings.colors), area);
+    }
 
-    f.render_widget(input_bar, top[0]);
-    f.render_widget(status, top[1]);
-    f.render_stateful_widget(items, chunks[1], &mut app.list.state);
+    // bold and the like are styling too, `NO_COLOR` asks for none of it
+    if !config::colors_enabled() {
+        for cell in &mut f.buffer_mut().content {
+            cell.modifier = Modifier::empty();
+        }
+    }
+}
+
+/// A `width` x `height` rect in the middle of `area`, shrunk to fit
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    )
+}
+
+/// A walk running on another thread
+struct Walker {
+    /// what was found so far, disconnected once the walk is done
+    found: Receiver<Found>,
+    /// set once the walk is done
+    walked: Arc<OnceLock<Walked>>,
+}
+
+/// How a walk ended
+#[derive(Default)]
+struct Walked {
+    /// directories sent
+    found: usize,
+    /// stopped at its `WalkLimit` rather than running out of directories
+    truncated: bool,
+    /// entries that couldn't be read, e.g. for lack of permissions, their
+    /// subtrees are missing
+    errors: usize,
+}
 
-    f.set_cursor(top[0].x + app.cursor_pos as u16 + 3, top[0].y);
+/// Where a walk gives up so a huge tree can't keep it going forever
+#[derive(Clone, Copy, Default)]
+struct WalkLimit {
+    /// directories found, `0` for no limit
+    max_nodes: usize,
+    max_time: Option<Duration>,
+}
+
+impl WalkLimit {
+    fn new(settings: &Settings) -> WalkLimit {
+        WalkLimit {
+            max_nodes: settings.max_nodes,
+            max_time: (settings.max_time > 0).then(|| Duration::from_secs(settings.max_time)),
+        }
+    }
+
+    fn timed_out(&self, started: Instant) -> bool {
+        self.max_time
+            .is_some_and(|max_time| started.elapsed() >= max_time)
+    }
+}
+
+/// Starts walking `paths` on another thread, zoxide's directories are sent
+/// first when enabled. The walker stops on its own once the receiver is
+/// dropped so it's never joined
+fn spawn_walker(paths: Arc<PathList>, settings: &Settings) -> Walker {
+    let (tx, rx) = mpsc::channel();
+    let done = Arc::new(OnceLock::new());
+    let walker = Walker {
+        found: rx,
+        walked: Arc::clone(&done),
+    };
+    let zoxide = settings.zoxide;
+    let show_hidden = settings.show_hidden;
+    let limit = WalkLimit::new(settings);
+    std::thread::spawn(move || {
+        if zoxide {
+            let source = Arc::new(Source {
+                name: ZOXIDE_SOURCE.to_string(),
+                color: None,
+                session_name: None,
+                specificity: 0,
+            });
+            for (dir, dir_name) in zoxide::query() {
+                if tx.send((dir, dir_name, Arc::clone(&source))).is_err() {
+                    return;
+                }
+            }
+        }
+        let walked = expand_paths(&paths, tx, show_hidden, limit);
+        log::info!(
+            "walked {} entries, found {} with {} errors{}",
+            paths.entries.len(),
+            walked.found,
+            walked.errors,
+            if walked.truncated {
+                " before the limit"
+            } else {
+                ""
+            }
+        );
+        _ = done.set(walked);
+    });
+    walker
 }
 
-fn expand_paths(paths: PathList) -> Vec<(String, String)> {
-    let mut path_items = Vec::new();
-    for path in paths.entries {
-        let dirs: Vec<(String, String)> = WalkDir::new(path.path)
+/// Walks every entry and streams what it finds through `tx`, names that aren't
+/// valid utf8 are converted lossily. Entries with a glob pattern walk every
+/// directory it matches, all under the entry as their source. Stops early if
+/// the receiving end is gone. Hidden directories are pruned unless
+/// `show_hidden` or the entry's own `show_hidden` is set, entries that can't
+/// be read are counted and skipped
+fn expand_paths(
+    paths: &PathList,
+    tx: Sender<Found>,
+    show_hidden: bool,
+    limit: WalkLimit,
+) -> Walked {
+    let started = Instant::now();
+    let mut walked = Walked::default();
+    let errors = Cell::new(0);
+    let bases = paths.entries.iter().flat_map(|path| {
+        let source = Arc::new(Source {
+            name: path.path.to_string_lossy().into_owned(),
+            color: path.color(),
+            session_name: path.session_name.clone(),
+            specificity: path.path.components().count(),
+        });
+        glob::expand(&path.path)
+            .into_iter()
+            .map(move |base| (path, Arc::clone(&source), base))
+    });
+    'walk: for (path, source, base) in bases {
+        let dirs = WalkDir::new(base)
             .min_depth(path.min_depth)
             .max_depth(path.max_depth)
+            // walkdir detects symlink loops on its own when following links
+            // and yields them as errors, which are counted below
+            .follow_links(path.follow_links)
             .into_iter()
+            // prune excluded dirs so their subtrees are never walked, the entry
+            // itself is always kept even if it's hidden
+            .filter_entry(|entry| {
+                let name = entry.file_name();
+                entry.depth() == 0
+                    || !entry.file_type().is_dir()
+                    || (!path.is_excluded(name)
+                        && (show_hidden || path.show_hidden || !is_hidden(name)))
+            })
+            // checked on every entry since a directory full of files takes
+            // time as well
+            .take_while(|_| !limit.timed_out(started))
             .filter_map(|item| {
-                let entry = item.ok()?;
-                let path = entry.path().to_owned();
-                if entry.file_type().is_dir() {
-                    let full_path = path.to_str()?.to_string();
-                    let dir_name = path.file_name()?.to_str()?.to_string();
-                    Some((full_path, dir_name))
+                let entry = item
+                    .map_err(|err| {
+                        log::debug!("skipped: {}", err);
+                        errors.set(errors.get() + 1);
+                    })
+                    .ok()?;
+                let file_type = entry.file_type();
+                if file_type.is_dir() || (path.include_files && file_type.is_file()) {
+                    let dir_name = entry.file_name().to_string_lossy().into_owned();
+                    Some((entry.into_path(), dir_name, Arc::clone(&source)))
                 } else {
                     None
                 }
-            })
-            .collect();
+            });
 
-        path_items.extend(dirs);
+        for dir in dirs {
+            if limit.max_nodes != 0 && walked.found >= limit.max_nodes {
+                walked.truncated = true;
+                break 'walk;
+            }
+            if tx.send(dir).is_err() {
+                break 'walk;
+            }
+            walked.found += 1;
+        }
+        if limit.timed_out(started) {
+            walked.truncated = true;
+            break;
+        }
     }
-    path_items
+    walked.errors = errors.get();
+    walked
+}
+
+/// Dot directories like `.config`
+fn is_hidden(name: &OsStr) -> bool {
+    name.to_string_lossy().starts_with('.')
+}
+
+/// Walks `paths` and matches every directory found against `query` the way
+/// the picker does, with the default matcher. Returns `(fullpath, dir_name)`
+/// pairs best match first, an empty query orders them by `frecency`
+pub fn search_paths(paths: PathList, query: &str, frecency: &Frecency) -> Vec<(String, String)> {
+    let (tx, rx) = mpsc::channel();
+    expand_paths(&paths, tx, false, WalkLimit::default());
+
+    let mut seen = HashSet::new();
+    let items: Vec<PathItem> = rx
+        .into_iter()
+        .filter(|(dir, ..)| seen.insert(dir.clone()))
+        .map(|(dir, path, source)| PathItem::new(dir, path, source, frecency))
+        .collect();
+    let (matches, _) = top_matches(
+        &items,
+        &Matcher::new(MatcherKind::default(), false),
+        query,
+        0,
+    );
+    matches
+        .into_iter()
+        .map(|item| (item.fullpath.to_string(), item.path.to_string()))
+        .collect()
 }
 
 fn init_terminal() -> Result<Term, anyhow::Error> {
     enable_raw_mode()?;
-    let mut stdout = std::io::stdout();
-    execute!(stdout, EnterAlternateScreen)?;
-    let backend = CrosstermBackend::new(stdout);
+    let mut output = terminal_output()?;
+    execute!(output, EnterAlternateScreen)?;
+    let backend = CrosstermBackend::new(output);
     let terminal = Terminal::new(backend)?;
     Ok(terminal)
 }
 
+/// Safe to call more than once, e.g. from the panic hook and again after the
+/// picker returned
 pub fn reset_terminal() -> Result<(), anyhow::Error> {
     disable_raw_mode()?;
-    execute!(std::io::stdout(), LeaveAlternateScreen)?;
+    leave_screen(&mut terminal_output()?)
+}
+
+/// Switches back from the alternate screen, a terminal that isn't on it
+/// ignores this
+fn leave_screen(output: &mut impl Write) -> Result<(), anyhow::Error> {
+    execute!(output, LeaveAlternateScreen)?;
     Ok(())
 }
 
-impl<'a> From<&'a Vec<(String, String)>> for StatefulList<'a> {
-    fn from(value: &'a Vec<(String, String)>) -> Self {
+/// Where the picker is drawn, stdout unless it is captured like in
+/// `cd "$(tmux-fzy --print)"`, then the terminal itself
+fn terminal_output() -> Result<Box<dyn Write>, anyhow::Error> {
+    let stdout = std::io::stdout();
+    if stdout.is_terminal() {
+        return Ok(Box::new(stdout));
+    }
+    let tty = OpenOptions::new()
+        .write(true)
+        .open("/dev/tty")
+        .map_err(|e| anyhow::anyhow!(e))?;
+    Ok(Box::new(tty))
+}
+
+impl From<(Vec<Found>, &Frecency)> for StatefulList {
+    fn from((value, frecency): (Vec<Found>, &Frecency)) -> Self {
         let mut list = StatefulList::default();
-        for item in value {
-            list.items.push(PathItem {
-                path: &item.1,
-                fullpath: &item.0,
-                score: 0,
-                indices: vec![],
-            });
+        for (dir, path, source) in value {
+            list.items.push(PathItem::new(dir, path, source, frecency));
         }
+        sort_items(&mut list.items);
         if !list.items.is_empty() {
             list.state.select(Some(0))
         }
@@ -225,21 +727,195 @@ impl<'a> From<&'a Vec<(String, String)>> for StatefulList<'a> {
     }
 }
 
-impl<'a> Eq for PathItem<'a> {}
-impl<'a> PartialEq for PathItem<'a> {
+impl PathItem {
+    fn new(dir: PathBuf, path: String, source: Arc<Source>, frecency: &Frecency) -> Self {
+        let fullpath = dir.to_string_lossy();
+        let frecency = frecency.score(&fullpath);
+        PathItem {
+            path: Arc::from(path),
+            fullpath: Arc::from(fullpath),
+            dir: Arc::from(dir),
+            score: frecency,
+            indices: vec![],
+            frecency,
+            running: false,
+            pinned: false,
+            marked: false,
+            source,
+        }
+    }
+
+    /// Name of the item's session, from its entry's template if it has one
+    fn session_name(&self) -> Result<String, anyhow::Error> {
+        match &self.source.session_name {
+            Some(template) => {
+                // for a pattern like `~/work/*` the root is the directory it
+                // matched, not the pattern
+                let entry = Path::new(&self.source.name);
+                let root = glob::base(entry, &self.dir).unwrap_or_else(|| entry.to_path_buf());
+                session_name_for(template, &root, &self.dir)
+            }
+            None => default_session_name(&self.fullpath),
+        }
+    }
+
+    /// Returns a scored copy of the item if it matches `query`.
+    ///
+    /// The query is split on whitespace and every token has to match: a bare
+    /// token is matched fuzzily, `'token` has to appear literally and `!token`
+    /// must not appear. Score and highlighted indices come from the fuzzy
+    /// tokens plus a bonus for names starting with or containing them as is,
+    /// without any the item is ordered by frecency. With
+    /// `path_segments` fuzzy tokens match the whole path instead of the name
+    fn match_query(&self, matcher: &Matcher, query: &str) -> Option<PathItem> {
+        let mut score = 0;
+        let mut indices = Vec::new();
+        let mut fuzzy = false;
+
+        for token in query.split_whitespace() {
+            if let Some(negated) = token.strip_prefix('!') {
+                if !negated.is_empty() && contains(&self.path, negated) {
+                    return None;
+                }
+            } else if let Some(exact) = token.strip_prefix('\'') {
+                if !contains(&self.path, exact) {
+                    return None;
+                }
+            } else {
+                let (token_score, token_indices) = if matcher.path_segments {
+                    let (token_score, token_indices) =
+                        matcher.path_indices(&self.fullpath, token)?;
+                    // only the name is drawn, keep the matches that fall in it
+                    let name_start = self.fullpath.chars().count() - self.path.chars().count();
+                    let token_indices = token_indices
+                        .into_iter()
+                        .filter_map(|i| i.checked_sub(name_start))
+                        .collect();
+                    (token_score, token_indices)
+                } else {
+                    matcher.fuzzy_indices(&self.path, token)?
+                };
+                score += token_score + name_bonus(&self.path, token);
+                indices.extend(token_indices);
+                fuzzy = true;
+            }
+        }
+
+        indices.sort_unstable();
+        indices.dedup();
+        Some(PathItem {
+            path: Arc::clone(&self.path),
+            fullpath: Arc::clone(&self.fullpath),
+            dir: Arc::clone(&self.dir),
+            score: if fuzzy { score } else { self.frecency },
+            indices,
+            frecency: self.frecency,
+            running: self.running,
+            pinned: self.pinned,
+            marked: self.marked,
+            source: Arc::clone(&self.source),
+        })
+    }
+}
+
+/// Whether everything matching `extended` also matches `query`. Appending
+/// only adds or tightens tokens, except for a `!` token which excludes less
+/// the longer it gets
+fn narrows(query: &str, extended: &str) -> bool {
+    let Some(appended) = extended.strip_prefix(query) else {
+        return false;
+    };
+    let extends_last_token =
+        !query.ends_with(char::is_whitespace) && !appended.starts_with(char::is_whitespace);
+    let last_token = query.split_whitespace().last().unwrap_or_default();
+    !(extends_last_token && last_token.starts_with('!'))
+}
+
+/// Substring check that is case-insensitive unless `needle` has uppercase
+/// chars, the same smart case the fuzzy matcher uses
+fn contains(haystack: &str, needle: &str) -> bool {
+    if needle.chars().any(char::is_uppercase) {
+        haystack.contains(needle)
+    } else {
+        haystack.to_lowercase().contains(needle)
+    }
+}
+
+/// Sorts best match first
+fn sort_items(items: &mut [PathItem]) {
+    items.par_sort_unstable_by(|a, b| b.cmp(a));
+}
+
+/// Matches `items` against `query` keeping only the best `max_results`
+/// (`0` keeps everything), returns them sorted along with the total number of
+/// matches. Each thread keeps its own bounded min-heap so the full set of
+/// matches is never collected
+fn top_matches(
+    items: &[PathItem],
+    matcher: &Matcher,
+    query: &str,
+    max_results: usize,
+) -> (Vec<PathItem>, usize) {
+    let max_results = if max_results == 0 {
+        usize::MAX
+    } else {
+        max_results
+    };
+    let push = |heap: &mut BinaryHeap<Reverse<PathItem>>, item: PathItem| {
+        heap.push(Reverse(item));
+        if heap.len() > max_results {
+            heap.pop();
+        }
+    };
+
+    let (heap, matched) = items
+        .par_iter()
+        .filter_map(|item| item.match_query(matcher, query))
+        .fold(
+            || (BinaryHeap::new(), 0),
+            |(mut heap, matched), item| {
+                push(&mut heap, item);
+                (heap, matched + 1)
+            },
+        )
+        .reduce(
+            || (BinaryHeap::new(), 0),
+            |(mut heap, matched), (other, other_matched)| {
+                for Reverse(item) in other {
+                    push(&mut heap, item);
+                }
+                (heap, matched + other_matched)
+            },
+        );
+
+    let mut items: Vec<PathItem> = heap.into_iter().map(|Reverse(item)| item).collect();
+    sort_items(&mut items);
+    (items, matched)
+}
+
+impl Eq for PathItem {}
+impl PartialEq for PathItem {
     fn eq(&self, other: &Self) -> bool {
-        self.score.eq(&other.score)
+        self.cmp(other).is_eq()
     }
 }
 
-impl<'a> Ord for PathItem<'a> {
+/// Orders pinned items above the rest and then by score, ties are broken
+/// alphabetically by `path` and then `fullpath`. Those comparisons are reversed
+/// so that with a higher score being greater, "a" is also greater than "b" and
+/// comes first in a descending sort
+impl Ord for PathItem {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.score.cmp(&other.score)
+        self.pinned
+            .cmp(&other.pinned)
+            .then_with(|| self.score.cmp(&other.score))
+            .then_with(|| other.path.cmp(&self.path))
+            .then_with(|| other.fullpath.cmp(&self.fullpath))
     }
 }
-impl<'a> PartialOrd for PathItem<'a> {
+impl PartialOrd for PathItem {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.score.cmp(&other.score))
+        Some(self.cmp(other))
     }
 }
 
@@ -266,127 +942,1185 @@ impl Spinner {
     }
 }
 
-impl<'a> App<'a> {
-    fn new(list: StatefulList<'a>, colors: Colors, len: usize) -> Self {
+impl App {
+    fn new(list: StatefulList, settings: Settings, len: usize, paths: Arc<PathList>) -> Self {
+        // not being able to list sessions only loses the markers
+        let sessions = tmux::list_sessions()
+            .unwrap_or_default()
+            .into_iter()
+            .collect();
+        App::with_state(
+            list,
+            settings,
+            len,
+            paths,
+            sessions,
+            Pins::load(),
+            Frecency::load(),
+        )
+    }
+
+    /// Like `new` with the running sessions, pins and frecency given instead
+    /// of asked from tmux and read from the cache
+    fn with_state(
+        list: StatefulList,
+        settings: Settings,
+        len: usize,
+        paths: Arc<PathList>,
+        sessions: HashSet<String>,
+        pins: Pins,
+        frecency: Frecency,
+    ) -> Self {
+        let walker = spawn_walker(Arc::clone(&paths), &settings);
         App {
             running: true,
             input: String::new(),
             cursor_pos: 0,
             total_items: len,
             list,
-            colors,
+            settings,
             loaded: false,
             spinner: Spinner::default(),
+            frecency,
+            pins,
+            rename: None,
+            confirm_kill: None,
+            help: false,
+            launched: false,
+            print: false,
+            chosen: Vec::new(),
+            walker,
+            depth_offset: 0,
+            paths,
+            warnings: Vec::new(),
+            viewport_rows: 0,
+            mode: Mode::Insert,
+            pending_key: None,
+            message: None,
+            sessions,
+            sessions_fetch: None,
+            sessions_fetched_at: Instant::now(),
+            seen: HashMap::new(),
+            paths_modified: config::paths_modified(),
+            typed_at: None,
+            clipboard: None,
         }
     }
 
-    fn refresh(&mut self) {
-        let matcher = fuzzy_matcher::skim::SkimMatcherV2::default();
+    fn selected_item(&self) -> Result<Option<&PathItem>, anyhow::Error> {
+        match self.list.state.selected() {
+            Some(i) => match self.list.items.get(i) {
+                Some(item) => Ok(Some(item)),
+                None => Err(anyhow::anyhow!("Indexing Failed")),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Starts or switches to a session for the selected item and quits,
+    /// `session_name` overrides the name derived from the directory
+    fn open_selected(&mut self, session_name: Option<String>) -> Result<(), anyhow::Error> {
+        let Some(item) = self.selected_item()? else {
+            return Ok(());
+        };
+        let fullpath = Arc::clone(&item.fullpath);
+        let dir = Arc::clone(&item.dir);
+        let session_name = match session_name {
+            Some(name) => name,
+            None => item.session_name()?,
+        };
+        self.running = false;
+        let entry = self.paths.entry_for(&dir);
+        start_tmux(&dir, &session_name, entry, &mut self.warnings)?;
+        self.launched = true;
+        frecency::record(&fullpath);
+        Ok(())
+    }
 
-        let new_items: BinaryHeap<PathItem> = self
+    /// Creates sessions for every marked directory and exits, without any
+    /// marks the selected one is opened
+    fn open(&mut self) -> Result<(), anyhow::Error> {
+        if self.print {
+            return self.choose();
+        }
+        let marked = self
             .list
-            .items
-            .par_iter()
-            .filter_map(|item| {
-                if let Some((score, indices)) = matcher.fuzzy_indices(item.path, &self.input) {
-                    return Some(PathItem {
-                        path: item.path,
-                        fullpath: item.fullpath,
-                        score,
-                        indices,
-                    });
-                }
-                None
+            .corpus()
+            .iter()
+            .filter(|item| item.marked)
+            .map(|item| {
+                Ok((
+                    Arc::clone(&item.fullpath),
+                    Arc::clone(&item.dir),
+                    item.session_name()?,
+                ))
             })
+            .collect::<Result<Vec<_>, anyhow::Error>>()?;
+        if marked.is_empty() {
+            return self.open_selected(None);
+        }
+
+        self.running = false;
+        for (fullpath, dir, session_name) in marked {
+            if tmux::has_session(&session_name)? {
+                continue;
+            }
+            let entry = self.paths.entry_for(&dir);
+            create_session(&session_name, &dir, entry, &mut self.warnings)?;
+            frecency::record(&fullpath);
+        }
+        self.launched = true;
+        Ok(())
+    }
+
+    /// Picks the marked directories, or the selected one without marks, and
+    /// exits so they can be printed
+    fn choose(&mut self) -> Result<(), anyhow::Error> {
+        let mut chosen: Vec<(Arc<str>, PathBuf)> = self
+            .list
+            .corpus()
+            .iter()
+            .filter(|item| item.marked)
+            .map(|item| (Arc::clone(&item.fullpath), item.dir.to_path_buf()))
             .collect();
+        if chosen.is_empty() {
+            let selected = self.selected_item()?;
+            chosen
+                .extend(selected.map(|item| (Arc::clone(&item.fullpath), item.dir.to_path_buf())));
+        }
+        if chosen.is_empty() {
+            return Ok(());
+        }
+
+        for (fullpath, dir) in chosen {
+            frecency::record(&fullpath);
+            self.chosen.push(dir);
+        }
+        self.running = false;
+        self.launched = true;
+        Ok(())
+    }
+
+    /// Marks or unmarks the selected directory and moves to the next one
+    fn toggle_mark(&mut self) -> Result<(), anyhow::Error> {
+        let Some(item) = self.selected_item()? else {
+            return Ok(());
+        };
+        let fullpath = Arc::clone(&item.fullpath);
+        let marked = !item.marked;
+
+        let list = &mut self.list;
+        let snapshots = list.history.iter_mut().map(|snapshot| &mut snapshot.items);
+        for item in snapshots.chain([&mut list.items]).flatten() {
+            if item.fullpath == fullpath {
+                item.marked = marked;
+            }
+        }
+        list.next();
+        Ok(())
+    }
+
+    /// Opens the `n`th directory drawn in the list like Enter would, counting
+    /// from 0 at the best match, the top or with `bottom_up` the bottom.
+    /// Ignored when fewer are drawn
+    fn open_visible(&mut self, n: usize) -> Result<(), anyhow::Error> {
+        let grouped = self.settings.group_by_source;
+        let bottom_up = self.settings.bottom_up;
+        let Some(i) = self
+            .list
+            .visible_item(n, grouped, bottom_up, self.viewport_rows)
+        else {
+            return Ok(());
+        };
+        self.list.state.select(Some(i));
+        self.open()
+    }
+
+    /// Creates a session for the selected directory in the background and
+    /// keeps the picker open
+    fn create_selected(&mut self) -> Result<(), anyhow::Error> {
+        let Some(item) = self.selected_item()? else {
+            return Ok(());
+        };
+        let fullpath = Arc::clone(&item.fullpath);
+        let dir = Arc::clone(&item.dir);
+        let session_name = item.session_name()?;
+        if tmux::has_session(&session_name)? {
+            self.notify(format!("Session '{}' already exists", session_name));
+            return Ok(());
+        }
+
+        let entry = self.paths.entry_for(&dir);
+        create_session(&session_name, &dir, entry, &mut self.warnings)?;
+        frecency::record(&fullpath);
+        self.notify(format!("Created session '{}'", session_name));
+        self.refresh_sessions();
+        Ok(())
+    }
+
+    /// Copies the selected path to the clipboard, failing to do so (e.g. on a
+    /// headless system) only shows a message
+    fn copy_selected(&mut self) -> Result<(), anyhow::Error> {
+        let Some(item) = self.selected_item()? else {
+            return Ok(());
+        };
+        let fullpath = item.fullpath.to_string();
+        let clipboard = match self.clipboard.take() {
+            Some(clipboard) => Ok(clipboard),
+            None => arboard::Clipboard::new(),
+        };
+        let copied = clipboard.and_then(|mut clipboard| {
+            let copied = clipboard.set_text(fullpath);
+            self.clipboard = Some(clipboard);
+            copied
+        });
+        match copied {
+            Ok(()) => self.notify("Copied to clipboard".to_string()),
+            Err(err) => self.notify(format!("Failed to copy: {}", err)),
+        }
+        Ok(())
+    }
+
+    /// Jumps back to the previously used session and closes the picker, only
+    /// possible from inside tmux
+    fn switch_last(&mut self) -> Result<(), anyhow::Error> {
+        if !tmux::env() {
+            self.notify("Not inside tmux, there is no client to switch".to_string());
+        } else if tmux::switch_last()? {
+            self.running = false;
+            self.launched = true;
+        } else {
+            self.notify("There is no previous session".to_string());
+        }
+        Ok(())
+    }
+
+    fn notify(&mut self, message: String) {
+        self.message = Some((message, Instant::now()));
+    }
+
+    fn insert_key(&mut self, code: KeyCode, modifiers: KeyModifiers) -> Result<(), anyhow::Error> {
+        match (code, modifiers) {
+            (KeyCode::Char(c), KeyModifiers::NONE) => self.insert_char(c),
+            (KeyCode::Char(c), KeyModifiers::SHIFT) => self.insert_char(c.to_ascii_uppercase()),
+            (KeyCode::Backspace, KeyModifiers::NONE) => self.delete_char(),
+            (KeyCode::Char('w'), KeyModifiers::CONTROL) => self.delete_word(),
+            (KeyCode::Left, KeyModifiers::NONE) => self.move_cursor_left(),
+            (KeyCode::Right, KeyModifiers::NONE) => self.move_cursor_right(),
+            (KeyCode::Esc, KeyModifiers::NONE) => self.mode = Mode::Normal,
+            _ => self.common_key(code, modifiers)?,
+        }
+        Ok(())
+    }
+
+    fn normal_key(&mut self, code: KeyCode, modifiers: KeyModifiers) -> Result<(), anyhow::Error> {
+        let pending = self.pending_key.take();
+        match (code, modifiers) {
+            (KeyCode::Char('j'), KeyModifiers::NONE) => {
+                self.move_on_screen(StatefulList::next, StatefulList::prev)
+            }
+            (KeyCode::Char('k'), KeyModifiers::NONE) => {
+                self.move_on_screen(StatefulList::prev, StatefulList::next)
+            }
+            (KeyCode::Char('g'), KeyModifiers::NONE) => {
+                self.move_on_screen(StatefulList::first, StatefulList::last)
+            }
+            (KeyCode::Char('G'), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+                self.move_on_screen(StatefulList::last, StatefulList::first)
+            }
+            (KeyCode::Char('i'), KeyModifiers::NONE) => self.mode = Mode::Insert,
+            (KeyCode::Char('?'), KeyModifiers::NONE | KeyModifiers::SHIFT) => self.help = true,
+            (KeyCode::Char('+'), KeyModifiers::NONE | KeyModifiers::SHIFT) => self.change_depth(1),
+            (KeyCode::Char('-'), KeyModifiers::NONE) => self.change_depth(-1),
+            (KeyCode::Char('d'), KeyModifiers::NONE) if pending == Some('d') => self.clear_input(),
+            (KeyCode::Char('d'), KeyModifiers::NONE) => self.pending_key = Some('d'),
+            (KeyCode::Char('q'), KeyModifiers::NONE) | (KeyCode::Esc, KeyModifiers::NONE) => {
+                self.running = false
+            }
+            _ => self.common_key(code, modifiers)?,
+        }
+        Ok(())
+    }
+
+    /// Runs `top_down` on the list when it is drawn top down and `bottom_up`
+    /// otherwise, so a key moves the selection the way it points on screen.
+    /// Drawn bottom up, Down moves toward the better matches
+    fn move_on_screen(
+        &mut self,
+        top_down: impl FnOnce(&mut StatefulList),
+        bottom_up: impl FnOnce(&mut StatefulList),
+    ) {
+        if self.settings.bottom_up {
+            bottom_up(&mut self.list)
+        } else {
+            top_down(&mut self.list)
+        }
+    }
+
+    /// Keys that do the same thing in every mode
+    fn common_key(&mut self, code: KeyCode, modifiers: KeyModifiers) -> Result<(), anyhow::Error> {
+        // these act on the list, which has to match what was typed
+        self.sync_query();
+        match (code, modifiers) {
+            (KeyCode::Char('c'), KeyModifiers::CONTROL) => self.running = false,
 
-        let items = std::mem::take(&mut self.list.items);
-        self.list.history.push(items);
-        self.list.items = new_items;
+            (KeyCode::Char('j'), KeyModifiers::CONTROL) | (KeyCode::Down, KeyModifiers::NONE) => {
+                self.move_on_screen(StatefulList::next, StatefulList::prev)
+            }
+
+            (KeyCode::Char('k'), KeyModifiers::CONTROL) | (KeyCode::Up, KeyModifiers::NONE) => {
+                self.move_on_screen(StatefulList::prev, StatefulList::next)
+            }
+
+            (KeyCode::Char('d'), KeyModifiers::CONTROL)
+            | (KeyCode::Down, KeyModifiers::CONTROL)
+            | (KeyCode::PageDown, KeyModifiers::NONE) => {
+                let page = self.viewport_rows;
+                self.move_on_screen(|list| list.scroll_next(page), |list| list.scroll_prev(page))
+            }
+
+            (KeyCode::Char('u'), KeyModifiers::CONTROL)
+            | (KeyCode::Up, KeyModifiers::CONTROL)
+            | (KeyCode::PageUp, KeyModifiers::NONE) => {
+                let page = self.viewport_rows;
+                self.move_on_screen(|list| list.scroll_prev(page), |list| list.scroll_next(page))
+            }
+
+            (KeyCode::Enter, KeyModifiers::NONE) => self.open()?,
+            (KeyCode::Tab, KeyModifiers::NONE) => self.toggle_mark()?,
+            (KeyCode::Char('r'), KeyModifiers::CONTROL) => self.start_rename()?,
+            (KeyCode::Char('y'), KeyModifiers::CONTROL) => self.copy_selected()?,
+            (KeyCode::Char('l'), KeyModifiers::CONTROL) => self.switch_last()?,
+            (KeyCode::Char('x'), KeyModifiers::CONTROL) => self.start_kill()?,
+            (KeyCode::Char('p'), KeyModifiers::CONTROL) => self.toggle_pin()?,
+            (KeyCode::Char('o'), KeyModifiers::CONTROL) => self.create_selected()?,
+            (KeyCode::Char('h'), KeyModifiers::CONTROL) => self.toggle_hidden(),
+            (KeyCode::Char(c @ '1'..='9'), KeyModifiers::ALT) => {
+                self.open_visible(c as usize - '1' as usize)?
+            }
 
-        let len = self.list.items.len();
-        match len {
-            0 => self.list.state.select(None),
-            i if i >= len => self.list.state.select(Some(0)),
             _ => {}
         }
+        Ok(())
+    }
+
+    /// Kills the selected directory's session, asking first unless
+    /// `confirm_kill` is turned off
+    fn start_kill(&mut self) -> Result<(), anyhow::Error> {
+        let Some(item) = self.selected_item()? else {
+            return Ok(());
+        };
+        let session_name = item.session_name()?;
+        if !tmux::has_session(&session_name)? {
+            self.notify(format!("No session named '{}'", session_name));
+        } else if self.settings.confirm_kill {
+            self.confirm_kill = Some(session_name);
+        } else {
+            self.kill_session(&session_name)?;
+        }
+        Ok(())
     }
 
+    /// Only `y` kills, every other key is swallowed while the prompt is open
+    fn confirm_kill_key(
+        &mut self,
+        code: KeyCode,
+        modifiers: KeyModifiers,
+    ) -> Result<(), anyhow::Error> {
+        match (code, modifiers) {
+            (KeyCode::Char('y'), KeyModifiers::NONE) => {
+                if let Some(session_name) = self.confirm_kill.take() {
+                    self.kill_session(&session_name)?;
+                }
+            }
+            (KeyCode::Char('n'), KeyModifiers::NONE) | (KeyCode::Esc, KeyModifiers::NONE) => {
+                self.confirm_kill = None
+            }
+            (KeyCode::Char('c'), KeyModifiers::CONTROL) => self.running = false,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn kill_session(&mut self, session_name: &str) -> Result<(), anyhow::Error> {
+        if tmux::kill_session(session_name)? {
+            self.notify(format!("Killed session '{}'", session_name));
+        } else {
+            self.notify(format!("Failed to kill session '{}'", session_name));
+        }
+        self.refresh_sessions();
+        Ok(())
+    }
+
+    /// Pins or unpins the selected directory and moves it accordingly in the
+    /// list and every snapshot
+    fn toggle_pin(&mut self) -> Result<(), anyhow::Error> {
+        let Some(item) = self.selected_item()? else {
+            return Ok(());
+        };
+        let fullpath = Arc::clone(&item.fullpath);
+        let pinned = match self.pins.toggle(&fullpath) {
+            Ok(pinned) => pinned,
+            Err(err) => {
+                self.notify(format!("Failed to save pins: {}", err));
+                return Ok(());
+            }
+        };
+        self.notify(if pinned { "Pinned" } else { "Unpinned" }.to_string());
+
+        let list = &mut self.list;
+        let snapshots = list.history.iter_mut().map(|snapshot| &mut snapshot.items);
+        for items in snapshots.chain([&mut list.items]) {
+            for item in items.iter_mut().filter(|item| item.fullpath == fullpath) {
+                item.pinned = pinned;
+            }
+            sort_items(items);
+        }
+        list.reselect(Some(fullpath));
+        Ok(())
+    }
+
+    /// Fetches the running sessions again and updates every item's marker
+    fn refresh_sessions(&mut self) {
+        // a fetch started before would be older than this one
+        self.sessions_fetch = None;
+        self.sessions_fetched_at = Instant::now();
+        self.set_sessions(tmux::list_sessions().unwrap_or_default());
+    }
+
+    /// Lists the running sessions on another thread every
+    /// `SESSIONS_INTERVAL` and applies the result once it is there, tmux can
+    /// take up to its timeout to answer
+    fn poll_sessions(&mut self) {
+        match self.sessions_fetch.as_ref().map(Receiver::try_recv) {
+            Some(Ok(sessions)) => {
+                self.sessions_fetch = None;
+                self.set_sessions(sessions);
+            }
+            Some(Err(TryRecvError::Empty)) => {}
+            // the thread is gone without sending, try again next time
+            Some(Err(TryRecvError::Disconnected)) => self.sessions_fetch = None,
+            None if self.sessions_fetched_at.elapsed() >= SESSIONS_INTERVAL => {
+                self.sessions_fetched_at = Instant::now();
+                let (tx, rx) = mpsc::channel();
+                std::thread::spawn(move || {
+                    _ = tx.send(tmux::list_sessions().unwrap_or_default());
+                });
+                self.sessions_fetch = Some(rx);
+            }
+            None => {}
+        }
+    }
+
+    /// Updates every item's marker, in the current list as well as the
+    /// snapshots of earlier queries
+    fn set_sessions(&mut self, sessions: Vec<String>) {
+        let sessions: HashSet<String> = sessions.into_iter().collect();
+        // nothing to do most of the time
+        if sessions == self.sessions {
+            return;
+        }
+        self.sessions = sessions;
+        let sessions = &self.sessions;
+        let list = &mut self.list;
+        let snapshots = list.history.iter_mut().map(|snapshot| &mut snapshot.items);
+        for item in snapshots.chain([&mut list.items]).flatten() {
+            item.running = item
+                .session_name()
+                .is_ok_and(|name| sessions.contains(&name));
+        }
+    }
+
+    /// Opens the rename prompt pre-filled with the default session name
+    fn start_rename(&mut self) -> Result<(), anyhow::Error> {
+        if let Some(item) = self.selected_item()? {
+            self.rename = Some(item.session_name()?);
+        }
+        Ok(())
+    }
+
+    fn rename_key(&mut self, code: KeyCode, modifiers: KeyModifiers) -> Result<(), anyhow::Error> {
+        let Some(name) = self.rename.as_mut() else {
+            return Ok(());
+        };
+        match (code, modifiers) {
+            (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => name.push(c),
+            (KeyCode::Backspace, KeyModifiers::NONE) => _ = name.pop(),
+            (KeyCode::Esc, KeyModifiers::NONE) => self.rename = None,
+            (KeyCode::Char('c'), KeyModifiers::CONTROL) => self.running = false,
+            (KeyCode::Enter, KeyModifiers::NONE) => {
+                let name = tmux::sanitize_session_name(name);
+                // keep the prompt open until there is something to use
+                if !name.is_empty() {
+                    self.rename = None;
+                    self.open_selected(Some(name))?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn refresh(&mut self) {
+        let selected = self.list.selected_fullpath();
+        self.filter();
+        self.list.reselect(selected);
+    }
+
+    /// Matches every item against `input`, the previous state goes to history.
+    /// When `input` only narrows the current query the current items are all
+    /// that can match, unless they were capped
+    fn filter(&mut self) {
+        let matcher = Matcher::new(self.settings.matcher, self.settings.path_segments);
+        let candidates = if narrows(&self.list.query, &self.input)
+            && self.list.matched <= self.list.items.len()
+        {
+            &self.list.items
+        } else {
+            self.list.corpus()
+        };
+
+        let (new_items, matched) =
+            top_matches(candidates, &matcher, &self.input, self.settings.max_results);
+
+        let list = &mut self.list;
+        list.history.push(Snapshot {
+            query: std::mem::replace(&mut list.query, self.input.clone()),
+            items: std::mem::replace(&mut list.items, new_items),
+            matched: std::mem::replace(&mut list.matched, matched),
+        });
+    }
+
+    /// Restores the snapshot built with the current `input`, if there is none
+    /// (a char was removed mid-query) it falls back to filtering the unfiltered
+    /// base snapshot again
     fn undo(&mut self) {
-        if let Some(items) = self.list.history.pop() {
-            let len = items.len();
-            if len != 0 {
-                self.list.state.select(Some(0))
+        let selected = self.list.selected_fullpath();
+        while let Some(snapshot) = self.list.history.pop() {
+            self.list.items = snapshot.items;
+            self.list.query = snapshot.query;
+            self.list.matched = snapshot.matched;
+            if self.list.query == self.input {
+                break;
+            }
+            if self.list.history.is_empty() {
+                self.filter();
+                break;
+            }
+        }
+        self.list.reselect(selected);
+    }
+
+    /// Byte offset of `cursor_pos` (which counts chars) inside `input`
+    fn cursor_byte_pos(&self) -> usize {
+        self.input
+            .char_indices()
+            .nth(self.cursor_pos)
+            .map(|(i, _)| i)
+            .unwrap_or(self.input.len())
+    }
+
+    fn insert_char(&mut self, c: char) {
+        let pos = self.cursor_byte_pos();
+        self.input.insert(pos, c);
+        self.cursor_pos += 1;
+        self.typed_at = Some(Instant::now());
+    }
+
+    /// Brings the list up to date with `input`. A query that was only typed
+    /// onto is filtered again, with a single history entry for the whole
+    /// burst, anything else goes back through the history
+    fn sync_query(&mut self) {
+        self.typed_at = None;
+        if self.input == self.list.query {
+            return;
+        }
+        if self.input.starts_with(&self.list.query) {
+            self.refresh();
+        } else {
+            self.undo();
+        }
+    }
+
+    /// Removes the char before the cursor
+    fn delete_char(&mut self) {
+        if self.cursor_pos == 0 {
+            return;
+        }
+        self.cursor_pos -= 1;
+        let pos = self.cursor_byte_pos();
+        self.input.remove(pos);
+        self.sync_query();
+    }
+
+    /// Removes the word before the cursor along with any whitespace between
+    /// it and the cursor, like readline's Ctrl-W. The list is rebuilt in one
+    /// step so the whole deletion is a single history entry
+    fn delete_word(&mut self) {
+        let end = self.cursor_byte_pos();
+        let start = self.input[..end]
+            .trim_end()
+            .char_indices()
+            .rev()
+            .find(|(_, c)| c.is_whitespace())
+            .map(|(i, c)| i + c.len_utf8())
+            .unwrap_or(0);
+        if start == end {
+            return;
+        }
+
+        self.cursor_pos -= self.input[start..end].chars().count();
+        self.input.replace_range(start..end, "");
+        self.sync_query();
+    }
+
+    /// Empties the query, the unfiltered list is restored from history
+    fn clear_input(&mut self) {
+        if self.input.is_empty() {
+            return;
+        }
+        self.input.clear();
+        self.cursor_pos = 0;
+        self.sync_query();
+    }
+
+    fn move_cursor_left(&mut self) {
+        self.cursor_pos = self.cursor_pos.saturating_sub(1);
+    }
+
+    fn move_cursor_right(&mut self) {
+        self.cursor_pos = (self.cursor_pos + 1).min(self.input.chars().count());
+    }
+
+    /// Reads the paths file again if it changed and starts walking it, the
+    /// directories found are merged into the list by `receive_paths`. A file
+    /// that doesn't parse keeps the current paths
+    fn reload_paths(&mut self) {
+        let modified = config::paths_modified();
+        if modified == self.paths_modified {
+            return;
+        }
+        self.paths_modified = modified;
+
+        match config::get_paths() {
+            Ok(paths) => self.set_paths(paths),
+            Err(err) => {
+                // parse errors span several lines, the first one says where
+                let err = err.to_string();
+                let reason = err.lines().next().unwrap_or_default();
+                self.notify(format!("Failed to reload paths: {}", reason));
+            }
+        }
+    }
+
+    /// Walks `paths` instead of the current entries. Added entries only add
+    /// directories, anything else starts over since some may have to go
+    fn set_paths(&mut self, paths: PathList) {
+        let added_only = self
+            .paths
+            .entries
+            .iter()
+            .all(|entry| paths.entries.contains(entry));
+        if !added_only {
+            self.list.clear(&self.input);
+            self.seen.clear();
+            self.total_items = 0;
+        }
+        self.paths = Arc::new(paths);
+        self.walk();
+    }
+
+    /// Walks the entries again with `depth_offset` applied, replacing any
+    /// walk that is still running
+    fn walk(&mut self) {
+        let entries = self
+            .paths
+            .entries
+            .iter()
+            .map(|entry| Entry {
+                max_depth: entry
+                    .max_depth
+                    .saturating_add_signed(self.depth_offset)
+                    .max(entry.min_depth),
+                ..entry.clone()
+            })
+            .collect();
+        let paths = Arc::new(PathList { entries });
+        self.walker = spawn_walker(paths, &self.settings);
+        self.loaded = false;
+        self.spinner.visible = true;
+    }
+
+    /// Walks one level deeper or shallower than configured. Going deeper only
+    /// adds directories, going shallower starts over since some have to go
+    fn change_depth(&mut self, delta: isize) {
+        let span = self
+            .paths
+            .entries
+            .iter()
+            .map(|entry| entry.max_depth.saturating_sub(entry.min_depth))
+            .max()
+            .unwrap_or(0);
+        let depth_offset = self.depth_offset + delta;
+        // below that no entry would change anymore
+        if depth_offset < -(span as isize) {
+            return;
+        }
+        self.depth_offset = depth_offset;
+
+        if delta < 0 {
+            self.list.clear(&self.input);
+            self.seen.clear();
+            self.total_items = 0;
+        }
+        self.walk();
+    }
+
+    /// Lists hidden directories or stops listing them, showing them only adds
+    /// directories while hiding them starts over
+    fn toggle_hidden(&mut self) {
+        self.settings.show_hidden = !self.settings.show_hidden;
+        if !self.settings.show_hidden {
+            self.list.clear(&self.input);
+            self.seen.clear();
+            self.total_items = 0;
+        }
+        self.notify(
+            if self.settings.show_hidden {
+                "Showing hidden directories"
+            } else {
+                "Hiding hidden directories"
             }
-            self.list.items = items;
+            .to_string(),
+        );
+        self.walk();
+    }
+
+    /// Drains whatever the walker has found so far and merges it into the
+    /// current list as well as every snapshot in the history, each filtered by
+    /// the query it was built with. A directory found more than once is listed
+    /// once, under the most specific entry
+    fn receive_paths(&mut self) {
+        let mut received = Vec::new();
+        // directories seen before that were found under a more specific entry
+        let mut moved: HashMap<Arc<Path>, Arc<Source>> = HashMap::new();
+        loop {
+            match self.walker.found.try_recv() {
+                Ok((dir, path, source)) => {
+                    let seen = self
+                        .seen
+                        .get_key_value(dir.as_path())
+                        .map(|(dir, &specificity)| (Arc::clone(dir), specificity));
+                    match seen {
+                        Some((dir, specificity)) => {
+                            if source.specificity > specificity {
+                                self.seen.insert(Arc::clone(&dir), source.specificity);
+                                moved.insert(dir, source);
+                            }
+                        }
+                        None => {
+                            let mut item = PathItem::new(dir, path, source, &self.frecency);
+                            item.running = item
+                                .session_name()
+                                .is_ok_and(|name| self.sessions.contains(&name));
+                            item.pinned = self.pins.contains(&item.fullpath);
+                            self.seen
+                                .insert(Arc::clone(&item.dir), item.source.specificity);
+                            received.push(item);
+                        }
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.loaded = true;
+                    self.spinner.visible = false;
+                    break;
+                }
+            }
+        }
+
+        if !moved.is_empty() {
+            let list = &mut self.list;
+            let snapshots = list.history.iter_mut().map(|snapshot| &mut snapshot.items);
+            let lists = snapshots.chain([&mut list.items, &mut received]);
+            for item in lists.flatten() {
+                if let Some(source) = moved.get(&item.dir) {
+                    item.source = Arc::clone(source);
+                }
+            }
+        }
+
+        if received.is_empty() {
+            return;
+        }
+        self.total_items += received.len();
+
+        let matcher = Matcher::new(self.settings.matcher, self.settings.path_segments);
+        let max_results = self.settings.max_results;
+        let extend = |query: &str, items: &mut Vec<PathItem>, matched: &mut usize| {
+            // the unfiltered list is the corpus, it is never capped
+            let max_results = if query.is_empty() || max_results == 0 {
+                usize::MAX
+            } else {
+                max_results
+            };
+            let (new_items, new_matched) = top_matches(&received, &matcher, query, max_results);
+            items.extend(new_items);
+            sort_items(items);
+            items.truncate(max_results);
+            *matched += new_matched;
+        };
+
+        let list = &mut self.list;
+        let selected = list.selected_fullpath();
+        for snapshot in list.history.iter_mut() {
+            extend(&snapshot.query, &mut snapshot.items, &mut snapshot.matched);
         }
+        extend(&list.query, &mut list.items, &mut list.matched);
+        list.reselect(selected);
     }
 }
 
-impl<'a> StatefulList<'a> {
+impl StatefulList {
+    /// Drops every item, keeping `query` so it applies to whatever is
+    /// received next. The unfiltered base snapshot is kept as the corpus
+    fn clear(&mut self, query: &str) {
+        self.items.clear();
+        self.matched = 0;
+        self.history.clear();
+        if !query.is_empty() {
+            self.history.push(Snapshot {
+                query: String::new(),
+                items: Vec::new(),
+                matched: 0,
+            });
+        }
+        self.query = query.to_string();
+        self.state.select(None);
+    }
+
+    /// All items, the bottom of the history is always the unfiltered list
+    fn corpus(&self) -> &[PathItem] {
+        match self.history.first() {
+            Some(snapshot) => &snapshot.items,
+            None => &self.items,
+        }
+    }
+
+    fn selected_fullpath(&self) -> Option<Arc<str>> {
+        let i = self.state.selected()?;
+        self.items.get(i).map(|item| Arc::clone(&item.fullpath))
+    }
+
+    /// Selects the item with `fullpath` if it is still in the list, otherwise
+    /// clamps the previous selection to the new bounds
+    fn reselect(&mut self, fullpath: Option<Arc<str>>) {
+        if self.items.is_empty() {
+            self.state.select(None);
+            return;
+        }
+
+        let found = fullpath
+            .and_then(|fullpath| self.items.iter().position(|item| item.fullpath == fullpath));
+        let i =
+            found.unwrap_or_else(|| self.state.selected().unwrap_or(0).min(self.items.len() - 1));
+        self.state.select(Some(i));
+    }
+
+    /// The selection if it points at an item, navigation does nothing
+    /// without one so it can't run past an empty list
+    fn selected(&self) -> Option<usize> {
+        self.state.selected().filter(|&i| i < self.items.len())
+    }
+
     fn next(&mut self) {
-        if let Some(i) = self.state.selected() {
+        if let Some(i) = self.selected() {
             if i < self.items.len() - 1 {
                 self.state.select(Some(i + 1));
+            } else if self.wrap {
+                self.state.select(Some(0));
             }
         }
     }
 
-    fn scroll_next(&mut self) {
-        if let Some(i) = self.state.selected() {
-            if i < self.items.len() - 5 {
-                self.state.select(Some(i + 5));
-            } else {
-                self.state.select(Some(self.items.len() - 1))
+    /// The items as drawn, with a header above each run of items from the
+    /// same source when `grouped`. Drawn `bottom_up` the rows are drawn in
+    /// reverse, so the header follows its run to still end up above it
+    fn rows(&self, grouped: bool, bottom_up: bool) -> Vec<Row<'_>> {
+        let mut rows = Vec::with_capacity(self.items.len());
+        let mut source: Option<&str> = None;
+        for item in &self.items {
+            // compared by name, a reload walks the same entries again
+            if grouped && source != Some(&item.source.name) {
+                match source {
+                    Some(previous) if bottom_up => rows.push(Row::Header(previous)),
+                    _ if !bottom_up => rows.push(Row::Header(&item.source.name)),
+                    _ => {}
+                }
+                source = Some(&item.source.name);
             }
+            rows.push(Row::Item(item));
+        }
+        if let (true, Some(last)) = (bottom_up, source) {
+            rows.push(Row::Header(last));
+        }
+        rows
+    }
+
+    /// Index of the `n`th item among the `height` rows drawn from the scroll
+    /// offset, `None` if fewer items are drawn
+    fn visible_item(
+        &self,
+        n: usize,
+        grouped: bool,
+        bottom_up: bool,
+        height: usize,
+    ) -> Option<usize> {
+        let rows = self.rows(grouped, bottom_up);
+        let offset = self.state.offset().min(rows.len());
+        let is_item = |row: &&Row| matches!(row, Row::Item(_));
+        let above = rows[..offset].iter().filter(is_item).count();
+        let drawn = rows[offset..].iter().take(height).filter(is_item).count();
+        (n < drawn).then_some(above + n)
+    }
+
+    fn first(&mut self) {
+        if !self.items.is_empty() {
+            self.state.select(Some(0));
+        }
+    }
+
+    fn last(&mut self) {
+        if !self.items.is_empty() {
+            self.state.select(Some(self.items.len() - 1));
+        }
+    }
+
+    fn scroll_next(&mut self, page: usize) {
+        if let Some(i) = self.selected() {
+            self.state
+                .select(Some((i + page.max(1)).min(self.items.len() - 1)));
         }
     }
 
     fn prev(&mut self) {
-        if let Some(i) = self.state.selected() {
+        if let Some(i) = self.selected() {
             if i != 0 {
                 self.state.select(Some(i - 1));
+            } else if self.wrap {
+                self.state.select(Some(self.items.len() - 1));
             }
         }
     }
 
-    fn scroll_prev(&mut self) {
-        if let Some(i) = self.state.selected() {
-            if i > 5 {
-                self.state.select(Some(i - 5));
-            } else {
-                self.state.select(Some(0))
-            }
+    fn scroll_prev(&mut self, page: usize) {
+        if let Some(i) = self.selected() {
+            self.state.select(Some(i.saturating_sub(page.max(1))));
         }
     }
 }
 
-pub fn start_tmux(path: &str) -> Result<(), anyhow::Error> {
+/// Offset that leaves at least `margin` rows between the `selected` row and
+/// either edge of the viewport, unless the list ends there anyway. Every
+/// value is in rows as drawn, headers included
+fn scroll_offset(
+    offset: usize,
+    selected: Option<usize>,
+    len: usize,
+    rows: usize,
+    margin: usize,
+) -> usize {
+    let Some(selected) = selected else {
+        return offset;
+    };
+    if rows == 0 {
+        return offset;
+    }
+    // a margin of half the viewport or more would keep it from moving
+    let margin = margin.min(rows.saturating_sub(1) / 2);
+    let offset = if selected < offset + margin {
+        selected.saturating_sub(margin)
+    } else if selected + margin >= offset + rows {
+        selected + margin + 1 - rows
+    } else {
+        offset
+    };
+    offset.min(len.saturating_sub(rows))
+}
+
+/// Session name derived from the directory name
+pub fn default_session_name(path: &str) -> Result<String, anyhow::Error> {
     let pathbuf = PathBuf::from(path);
     let session_name = pathbuf
         .file_name()
         .ok_or(anyhow::anyhow!("Failed to get session_name from filepath."))?
         .to_str()
         .ok_or(anyhow::anyhow!("session_name is not a valid utf8 string"))?;
+    Ok(tmux::sanitize_session_name(session_name))
+}
+
+/// Session name from an entry's `session_name` template, `{leaf}` is the
+/// directory's name, `{parent}` the name of the one above it and `{root}` the
+/// name of the entry's directory `root`. A placeholder that has nothing to
+/// refer to is left empty along with the separators next to it at either
+/// end, e.g. `{parent}-{leaf}` for `/app` is `app`. Falls back to the
+/// directory name if nothing is left
+pub fn session_name_for(template: &str, root: &Path, dir: &Path) -> Result<String, anyhow::Error> {
+    let name = |path: Option<&Path>| {
+        path.and_then(Path::file_name)
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+    let expanded = template
+        .replace("{leaf}", &name(Some(dir)))
+        .replace("{parent}", &name(dir.parent()))
+        .replace("{root}", &name(Some(root)));
+    let expanded = expanded.trim_matches(|c: char| c.is_whitespace() || "-_@".contains(c));
+    if expanded.is_empty() {
+        return default_session_name(&dir.to_string_lossy());
+    }
+    Ok(tmux::sanitize_session_name(expanded))
+}
+
+/// Creates a detached session, sets the entry's environment, lays out its
+/// panes and runs its command in it. For a file the session starts in its
+/// directory and the file is opened with the entry's `file_command`
+fn create_session(
+    session_name: &str,
+    path: &Path,
+    entry: Option<&Entry>,
+    warnings: &mut Vec<String>,
+) -> Result<(), anyhow::Error> {
+    let (dir, file) = split_file(path);
+    tmux::new_session_detach(session_name, dir)?;
+
+    for (key, value) in entry.iter().flat_map(|entry| &entry.env) {
+        let set = tmux::set_environment(session_name, key, value);
+        if !matches!(set, Ok(true)) {
+            warnings.push(format!(
+                "Failed to set '{}' in session '{}'",
+                key, session_name
+            ));
+        }
+    }
+
+    // a layout that doesn't work out still leaves a usable session
+    let panes = entry.and_then(|entry| entry.panes).unwrap_or(1);
+    for _ in 1..panes {
+        if !matches!(tmux::split_window(session_name, dir), Ok(true)) {
+            warnings.push(format!(
+                "Failed to split the window of session '{}'",
+                session_name
+            ));
+            break;
+        }
+    }
+    if let Some(layout) = entry.and_then(|entry| entry.layout.as_deref()) {
+        if !matches!(tmux::select_layout(session_name, layout), Ok(true)) {
+            warnings.push(format!(
+                "Failed to apply layout '{}' to session '{}'",
+                layout, session_name
+            ));
+        }
+    }
+
+    if let Some(command) = entry.and_then(|entry| entry.command.as_deref()) {
+        let sent = tmux::send_keys(session_name, command);
+        if !matches!(sent, Ok(true)) {
+            warnings.push(format!(
+                "Failed to run '{}' in session '{}'",
+                command, session_name
+            ));
+        }
+    }
+
+    let file_command = entry.and_then(|entry| entry.file_command.as_deref());
+    if let (Some(file), Some(file_command)) = (file, file_command) {
+        let command = format!("{} {}", file_command, shell_quote(&file.to_string_lossy()));
+        let sent = tmux::send_keys(session_name, &command);
+        if !matches!(sent, Ok(true)) {
+            warnings.push(format!(
+                "Failed to run '{}' in session '{}'",
+                command, session_name
+            ));
+        }
+    }
+
+    Ok(())
+}
 
+/// The directory a session for `path` starts in along with the file when
+/// `path` is one
+fn split_file(path: &Path) -> (&Path, Option<&Path>) {
+    match path.parent() {
+        Some(parent) if path.is_file() => (parent, Some(path)),
+        _ => (path, None),
+    }
+}
+
+/// Selects the entry's `target_window` in the session, creating it when it
+/// doesn't exist yet. If neither works the session stays in its current
+/// window
+fn select_target_window(
+    session_name: &str,
+    path: &Path,
+    entry: Option<&Entry>,
+    warnings: &mut Vec<String>,
+) {
+    let Some(window) = entry.and_then(|entry| entry.target_window.as_deref()) else {
+        return;
+    };
+    if matches!(tmux::select_window(session_name, window), Ok(true)) {
+        return;
+    }
+    let (dir, _) = split_file(path);
+    if !matches!(tmux::new_window(session_name, window, dir), Ok(true)) {
+        warnings.push(format!(
+            "Failed to open window '{}' in session '{}', staying in the current window",
+            window, session_name
+        ));
+    }
+}
+
+/// Wraps `s` in single quotes so the shell passes it on as one argument
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+pub fn start_tmux(
+    path: &Path,
+    session_name: &str,
+    entry: Option<&Entry>,
+    warnings: &mut Vec<String>,
+) -> Result<(), anyhow::Error> {
     let tmux_running = tmux::status()?;
     let tmux_env = tmux::env();
     let tmux_has_session = tmux::has_session(session_name)?;
 
+    // new sessions are always created detached so they can be set up before
+    // attaching to them. The window is selected beforehand too since
+    // attaching only returns once the client detaches
     match (tmux_running, tmux_env) {
-        (false, false) => tmux::new_session(session_name, path)?,
+        (false, false) => {
+            create_session(session_name, path, entry, warnings)?;
+            select_target_window(session_name, path, entry, warnings);
+            tmux::attach(session_name)?;
+        }
         (true, false) => {
-            if tmux_has_session {
-                tmux::attach(session_name)?;
-            } else {
-                tmux::new_session(session_name, path)?;
+            if !tmux_has_session {
+                create_session(session_name, path, entry, warnings)?;
             }
+            select_target_window(session_name, path, entry, warnings);
+            tmux::attach(session_name)?;
         }
         (true, true) => {
-            if tmux_has_session {
-                tmux::switch_client(session_name)?;
-            } else {
-                tmux::new_session_detach(session_name, path)?;
+            if !tmux_has_session {
+                create_session(session_name, path, entry, warnings)?;
+            }
+            select_target_window(session_name, path, entry, warnings);
+            // switching to the session we are in would only flicker
+            let current = tmux::current_session().unwrap_or_default();
+            if current.as_deref() != Some(session_name) {
                 tmux::switch_client(session_name)?;
             }
         }
@@ -395,3 +2129,455 @@ pub fn start_tmux(path: &str) -> Result<(), anyhow::Error> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{make_dirs, temp_dir, tmux_server};
+
+    fn item(dir: &str, score: i64) -> PathItem {
+        let dir = PathBuf::from(dir);
+        let name = dir.file_name().unwrap().to_string_lossy().into_owned();
+        let source = Arc::new(Source {
+            name: "/".to_string(),
+            color: None,
+            session_name: None,
+            specificity: 0,
+        });
+        PathItem {
+            score,
+            ..PathItem::new(dir, name, source, &Frecency::default())
+        }
+    }
+
+    fn fullpaths(items: &[PathItem]) -> Vec<&str> {
+        items.iter().map(|item| &*item.fullpath).collect()
+    }
+
+    #[test]
+    fn tie_order() {
+        let mut items = vec![
+            item("/x/b", 5),
+            item("/y/a", 5),
+            item("/x/z", 9),
+            item("/x/a", 5),
+            PathItem {
+                pinned: true,
+                ..item("/x/q", 1)
+            },
+        ];
+        sort_items(&mut items);
+        // pinned, then by score, then by name and full path
+        assert_eq!(fullpaths(&items), ["/x/q", "/x/z", "/x/a", "/y/a", "/x/b"]);
+
+        // equal scores cut off at the same items however the walk ordered them
+        let items: Vec<PathItem> = ["/y/a", "/x/c", "/x/b", "/x/a"]
+            .into_iter()
+            .map(|dir| item(dir, 0))
+            .collect();
+        let matcher = Matcher::new(MatcherKind::default(), false);
+        let (top, matched) = top_matches(&items, &matcher, "", 2);
+        assert_eq!(fullpaths(&top), ["/x/a", "/y/a"]);
+        assert_eq!(matched, 4);
+    }
+
+    #[test]
+    fn search() {
+        let root = temp_dir("tui-search");
+        make_dirs(
+            &root,
+            &["work/projects", "work/old-proj", "swap/temp", "misc/.proj"],
+        );
+        let paths = PathList {
+            entries: vec![Entry {
+                path: root.clone(),
+                min_depth: 1,
+                max_depth: 2,
+                ..Default::default()
+            }],
+        };
+
+        let pair = |dir: &str, leaf: &str| (root.join(dir).display().to_string(), leaf.to_string());
+        // the hidden directory isn't walked into, the prefix match ranks first
+        assert_eq!(
+            search_paths(paths, "proj", &Frecency::default()),
+            [
+                pair("work/projects", "projects"),
+                pair("work/old-proj", "old-proj")
+            ]
+        );
+        _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn session_names() {
+        let name = |template, root, dir| {
+            session_name_for(template, Path::new(root), Path::new(dir)).unwrap()
+        };
+        assert_eq!(name("{parent}-{leaf}", "/srv", "/srv/api/web"), "api-web");
+        assert_eq!(name("{root}_{leaf}", "/srv", "/srv/api/web"), "srv_web");
+        // nothing above `/app`, the separator goes with it
+        assert_eq!(name("{parent}-{leaf}", "/", "/app"), "app");
+        assert_eq!(name("{leaf}@{parent}", "/", "/app"), "app");
+        assert_eq!(name("{parent}", "/", "/app"), "app");
+        assert_eq!(name(" -{parent}_{leaf}@ ", "/", "/x/v1.2"), "x_v1_2");
+    }
+
+    #[test]
+    fn session_name_root_of_pattern() {
+        let root = temp_dir("tui-session-root");
+        make_dirs(&root, &["work/api/web"]);
+        let source = Arc::new(Source {
+            name: root.join("work/*").display().to_string(),
+            color: None,
+            session_name: Some("{root}-{leaf}".to_string()),
+            specificity: 0,
+        });
+        let item = PathItem::new(
+            root.join("work/api/web"),
+            "web".to_string(),
+            source,
+            &Frecency::default(),
+        );
+        assert_eq!(item.session_name().unwrap(), "api-web");
+        _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn query_tokens() {
+        let items = [
+            item("/w/projects", 0),
+            item("/w/old-proj", 0),
+            item("/w/Project-X", 0),
+            item("/w/notes", 0),
+        ];
+        let matcher = Matcher::new(MatcherKind::default(), false);
+        let matching = |query: &str| {
+            let mut matched: Vec<&str> = items
+                .iter()
+                .filter(|item| item.match_query(&matcher, query).is_some())
+                .map(|item| &*item.path)
+                .collect();
+            matched.sort_unstable();
+            matched
+        };
+
+        assert_eq!(matching("'proj"), ["Project-X", "old-proj", "projects"]);
+        // smart case, an uppercase char makes the token case sensitive
+        assert_eq!(matching("'Proj"), ["Project-X"]);
+        assert_eq!(matching("!proj"), ["notes"]);
+        assert_eq!(matching("proj !old"), ["Project-X", "projects"]);
+        assert_eq!(matching("'proj !old !x"), ["projects"]);
+        assert_eq!(matching("'old 'proj"), ["old-proj"]);
+        assert_eq!(matching("'proj !proj"), Vec::<&str>::new());
+        // a lone `'` or `!` doesn't filter anything yet
+        assert_eq!(matching("' !").len(), 4);
+
+        // exact and negated tokens don't score or highlight
+        let matched = items[1].match_query(&matcher, "'old").unwrap();
+        assert!(matched.indices.is_empty());
+        assert_eq!(matched.score, matched.frecency);
+        let matched = items[1].match_query(&matcher, "'old proj").unwrap();
+        assert_eq!(matched.indices, [4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn non_utf8_dir_is_listed() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let root = temp_dir("tui-non-utf8");
+        let odd = root.join(std::ffi::OsStr::from_bytes(b"caf\xe9"));
+        std::fs::create_dir(&odd).unwrap();
+        let paths = PathList {
+            entries: vec![Entry {
+                path: root.clone(),
+                min_depth: 1,
+                max_depth: 1,
+                ..Default::default()
+            }],
+        };
+
+        let (tx, rx) = mpsc::channel();
+        expand_paths(&paths, tx, false, WalkLimit::default());
+        let found: Vec<(PathBuf, String)> =
+            rx.into_iter().map(|(dir, name, _)| (dir, name)).collect();
+        // drawn lossily but the session still starts in the real directory
+        assert_eq!(found, [(odd, "caf\u{fffd}".to_string())]);
+        _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn navigating_an_empty_list() {
+        let mut list = StatefulList {
+            wrap: true,
+            ..Default::default()
+        };
+        for selected in [None, Some(0), Some(3)] {
+            list.state.select(selected);
+            list.next();
+            list.prev();
+            list.scroll_next(10);
+            list.scroll_prev(10);
+            list.first();
+            list.last();
+            assert_eq!(list.selected(), None);
+        }
+
+        // a stale selection is clamped once there are items again
+        list.items = vec![item("/a", 0), item("/b", 0)];
+        list.reselect(None);
+        assert_eq!(list.selected(), Some(1));
+        list.first();
+        list.prev();
+        assert_eq!(list.selected(), Some(1));
+        list.next();
+        assert_eq!(list.selected(), Some(0));
+        list.scroll_next(10);
+        assert_eq!(list.selected(), Some(1));
+
+        list.items.clear();
+        list.reselect(None);
+        assert_eq!(list.state.selected(), None);
+    }
+
+    #[test]
+    fn reset_twice() {
+        // raw mode was never enabled here, disabling it again is a no-op
+        disable_raw_mode().unwrap();
+        disable_raw_mode().unwrap();
+
+        let mut output = Vec::new();
+        leave_screen(&mut output).unwrap();
+        leave_screen(&mut output).unwrap();
+        assert_eq!(output, b"\x1b[?1049l\x1b[?1049l");
+    }
+
+    /// An app over `paths` once their walk is done
+    fn walked_app(paths: PathList) -> App {
+        let mut app = App::with_state(
+            StatefulList::default(),
+            Settings::default(),
+            0,
+            Arc::new(paths),
+            HashSet::new(),
+            Pins::default(),
+            Frecency::default(),
+        );
+        finish_walk(&mut app);
+        app
+    }
+
+    fn finish_walk(app: &mut App) {
+        while !app.loaded {
+            app.receive_paths();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn overlapping_entries_list_dirs_once() {
+        let root = temp_dir("tui-overlap");
+        make_dirs(&root, &["work/a", "work/b", "misc"]);
+        let outer = Entry {
+            path: root.clone(),
+            min_depth: 1,
+            max_depth: 2,
+            ..Default::default()
+        };
+        let inner = Entry {
+            path: root.join("work"),
+            min_depth: 1,
+            max_depth: 1,
+            ..Default::default()
+        };
+
+        // either order, the directories below `work` belong to it
+        for entries in [
+            vec![outer.clone(), inner.clone()],
+            vec![inner.clone(), outer.clone()],
+        ] {
+            let app = walked_app(PathList { entries });
+            let mut found: Vec<(&str, &str)> = app
+                .list
+                .items
+                .iter()
+                .map(|item| (&*item.path, &*item.source.name))
+                .collect();
+            found.sort_unstable();
+            let (root, work) = (root.to_str().unwrap(), root.join("work"));
+            let work = work.to_str().unwrap();
+            assert_eq!(
+                found,
+                [("a", work), ("b", work), ("misc", root), ("work", root)]
+            );
+            assert_eq!(app.total_items, 4);
+        }
+        _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn name_bonus_then_ties() {
+        assert_eq!(name_bonus("API-docs", "api"), 32);
+        assert_eq!(name_bonus("my-api", "api"), 16);
+        assert_eq!(name_bonus("a-big-project-index", "api"), 0);
+
+        let items = [
+            item("/x/a-big-project-index", 0),
+            item("/y/my-api", 0),
+            item("/y/api", 0),
+            item("/x/my-api", 0),
+            item("/x/api", 0),
+        ];
+        let matcher = Matcher::new(MatcherKind::default(), false);
+        let (top, _) = top_matches(&items, &matcher, "api", 0);
+        // prefix over substring over scattered, equal names by full path
+        assert_eq!(
+            fullpaths(&top),
+            [
+                "/x/api",
+                "/y/api",
+                "/x/my-api",
+                "/y/my-api",
+                "/x/a-big-project-index"
+            ]
+        );
+    }
+
+    #[test]
+    fn reloaded_paths_drop_removed_dirs() {
+        let root = temp_dir("tui-reload");
+        make_dirs(&root, &["work/a", "misc/b"]);
+        let entry = |dir: &str| Entry {
+            path: root.join(dir),
+            min_depth: 1,
+            max_depth: 1,
+            ..Default::default()
+        };
+        let names = |app: &App| {
+            let mut names: Vec<String> = app
+                .list
+                .items
+                .iter()
+                .map(|item| item.path.to_string())
+                .collect();
+            names.sort_unstable();
+            names
+        };
+
+        let mut app = walked_app(PathList {
+            entries: vec![entry("work")],
+        });
+        assert_eq!(names(&app), ["a"]);
+
+        app.set_paths(PathList {
+            entries: vec![entry("work"), entry("misc")],
+        });
+        finish_walk(&mut app);
+        assert_eq!(names(&app), ["a", "b"]);
+        assert_eq!(app.total_items, 2);
+
+        app.set_paths(PathList {
+            entries: vec![entry("misc")],
+        });
+        finish_walk(&mut app);
+        assert_eq!(names(&app), ["b"]);
+        assert_eq!(app.total_items, 1);
+
+        // narrowed, `misc` itself at depth 0 instead of what's inside it
+        app.set_paths(PathList {
+            entries: vec![Entry {
+                max_depth: 0,
+                min_depth: 0,
+                ..entry("misc")
+            }],
+        });
+        finish_walk(&mut app);
+        assert_eq!(names(&app), ["misc"]);
+        _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn question_mark_is_typed_in_insert_mode() {
+        let mut app = walked_app(PathList::default());
+        app.insert_key(KeyCode::Char('?'), KeyModifiers::SHIFT)
+            .unwrap();
+        assert_eq!(app.input, "?");
+        assert!(!app.help);
+
+        app.insert_key(KeyCode::Esc, KeyModifiers::NONE).unwrap();
+        app.normal_key(KeyCode::Char('?'), KeyModifiers::SHIFT)
+            .unwrap();
+        assert!(app.help);
+    }
+
+    #[test]
+    fn visible_items_count_from_the_best_match() {
+        let mut list = StatefulList {
+            items: vec![item("/a", 3), item("/b", 2), item("/c", 1)],
+            ..Default::default()
+        };
+        for bottom_up in [false, true] {
+            assert_eq!(list.visible_item(0, false, bottom_up, 10), Some(0));
+            assert_eq!(list.visible_item(2, false, bottom_up, 10), Some(2));
+            assert_eq!(list.visible_item(3, false, bottom_up, 10), None);
+        }
+
+        // scrolled by one, the first drawn is the second item
+        *list.state.offset_mut() = 1;
+        assert_eq!(list.visible_item(0, false, false, 2), Some(1));
+        assert_eq!(list.visible_item(1, false, false, 2), Some(2));
+        assert_eq!(list.visible_item(2, false, false, 2), None);
+    }
+
+    #[test]
+    fn marked_dir_opens_next_to_a_longer_session_name() {
+        if !tmux_server() {
+            return;
+        }
+        let root = temp_dir("tui-open-marked");
+        make_dirs(&root, &["marked-api"]);
+        tmux::new_session_detach("marked-api-gateway", &root).unwrap();
+
+        let mut app = walked_app(PathList {
+            entries: vec![Entry {
+                path: root.clone(),
+                min_depth: 1,
+                max_depth: 1,
+                ..Default::default()
+            }],
+        });
+        app.list.items[0].marked = true;
+        app.open().unwrap();
+        assert!(app.launched);
+        assert!(tmux::kill_session("marked-api").unwrap());
+        assert!(tmux::kill_session("marked-api-gateway").unwrap());
+        _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn creates_next_to_a_longer_session_name() {
+        if !tmux_server() {
+            return;
+        }
+        let root = temp_dir("tui-create");
+        make_dirs(&root, &["created-api"]);
+        tmux::new_session_detach("created-api-gateway", &root).unwrap();
+
+        let mut app = walked_app(PathList {
+            entries: vec![Entry {
+                path: root.clone(),
+                min_depth: 1,
+                max_depth: 1,
+                ..Default::default()
+            }],
+        });
+        app.list.state.select(Some(0));
+        app.create_selected().unwrap();
+        let message = app.message.as_ref().map(|(message, _)| message.as_str());
+        assert_eq!(message, Some("Created session 'created-api'"));
+        assert!(app.sessions.contains("created-api"));
+        assert!(tmux::kill_session("created-api").unwrap());
+        assert!(tmux::kill_session("created-api-gateway").unwrap());
+        _ = std::fs::remove_dir_all(root);
+    }
+}
@@ -1,86 +1,403 @@
-use std::collections::BinaryHeap;
+use std::ops::Range;
 
 use ratatui::prelude::*;
-use ratatui::widgets::{Block, List, ListDirection, ListItem, Padding, Paragraph};
+use ratatui::widgets::{Block, BorderType, Borders, List, ListDirection, ListItem, Paragraph};
 
-use crate::config::Colors;
-use crate::tui::{PathItem, Spinner};
+use crate::config::{BorderStyle, Colors};
+use crate::tui::{Mode, PathItem, Spinner};
 
-pub fn get_input_bar<'a>(input: &'a String, colors: &'a Colors) -> Paragraph<'a> {
+/// Gap between the prompt symbol and the query
+const PROMPT_GAP: &str = "  ";
+
+pub fn get_input_bar<'a>(input: &'a String, prompt: &'a str, colors: &'a Colors) -> Paragraph<'a> {
     let inputs: Vec<Span<'a>> = vec![
-        Span::styled("  ", Style::default().fg(colors.active)),
+        Span::styled(prompt, Style::default().fg(colors.active)),
+        Span::styled(PROMPT_GAP, Style::default().fg(colors.active)),
         Span::styled(input, Style::default().fg(colors.fg)),
     ];
     let line = Line::from(inputs);
+    Paragraph::new(line).style(Style::default().fg(colors.fg))
+}
+
+/// Drawn around the query and the prompts that replace it
+pub fn get_input_block(border_style: BorderStyle, colors: &Colors) -> Block<'static> {
+    with_borders(
+        Block::default().style(Style::default().fg(colors.active)),
+        border_style,
+        colors,
+    )
+}
+
+/// Drawn around the list, carries its title
+pub fn get_results_block<'a>(
+    title: &'a str,
+    border_style: BorderStyle,
+    colors: &Colors,
+) -> Block<'a> {
+    with_borders(
+        Block::default()
+            .title(title)
+            .style(Style::default().fg(colors.active)),
+        border_style,
+        colors,
+    )
+}
+
+fn with_borders<'a>(block: Block<'a>, border_style: BorderStyle, colors: &Colors) -> Block<'a> {
+    let border_type = match border_style {
+        BorderStyle::None => return block,
+        BorderStyle::Plain => BorderType::Plain,
+        BorderStyle::Rounded => BorderType::Rounded,
+    };
+    block
+        .borders(Borders::ALL)
+        .border_type(border_type)
+        .border_style(Style::default().fg(colors.border))
+}
+
+/// Columns taken up by the prompt in front of the query
+pub fn prompt_width(prompt: &str) -> usize {
+    Span::raw(prompt).width() + PROMPT_GAP.len()
+}
+
+pub const RENAME_PROMPT: &str = "session name: ";
+
+pub fn get_rename_bar<'a>(name: &'a str, colors: &'a Colors) -> Paragraph<'a> {
+    let line = Line::from(vec![
+        Span::styled(RENAME_PROMPT, Style::default().fg(colors.active)),
+        Span::styled(name, Style::default().fg(colors.fg)),
+    ]);
     Paragraph::new(line)
-        .style(Style::default().fg(colors.fg))
-        .block(
-            Block::default()
-                .style(Style::default().fg(colors.active))
-                .padding(Padding::new(0, 0, 0, 0)),
-        )
 }
 
+/// Keybindings shown by `?` in normal mode
+pub const HELP: &[(&str, &str)] = &[
+    ("Enter", "open the selected directory"),
+    ("Ctrl-O", "create its session in the background"),
+    ("Alt-1..Alt-9", "open the Nth directory shown"),
+    ("Tab", "mark, Enter then creates all marked"),
+    ("Ctrl-R", "open under a different name"),
+    ("Ctrl-X", "kill its session"),
+    ("Ctrl-L", "switch to the last session"),
+    ("Ctrl-P", "pin to the top"),
+    ("Ctrl-Y", "copy the path"),
+    ("Ctrl-H", "show/hide hidden directories"),
+    ("Up/Ctrl-K", "previous item"),
+    ("Down/Ctrl-J", "next item"),
+    ("Ctrl-U/Ctrl-D", "scroll a page up/down"),
+    ("Ctrl-W", "delete the word before the cursor"),
+    ("Esc", "normal mode, j/k g/G dd i q ?"),
+    ("+/-", "walk deeper/shallower, in normal mode"),
+    ("Ctrl-C", "quit"),
+];
+
+/// Wide enough for the longest line in `HELP` plus the borders
+pub const HELP_WIDTH: u16 = 54;
+
+pub fn get_help(colors: &Colors) -> Paragraph<'static> {
+    let lines: Vec<Line> = HELP
+        .iter()
+        .map(|(keys, action)| {
+            Line::from(vec![
+                Span::styled(format!("{:<15}", keys), Style::default().fg(colors.active)),
+                Span::styled(*action, Style::default().fg(colors.fg)),
+            ])
+        })
+        .collect();
+    Paragraph::new(lines).block(
+        Block::default()
+            .title("Keys")
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .border_style(Style::default().fg(colors.border)),
+    )
+}
+
+pub fn get_confirm_bar(question: String, colors: &Colors) -> Paragraph<'static> {
+    Paragraph::new(Span::styled(question, Style::default().fg(colors.active)))
+}
+
+/// Marks the currently selected row
+const HIGHLIGHT_SYMBOL: &str = "▪ ";
+
+/// A line in the result list
+pub enum Row<'a> {
+    /// names the source of the items below it, can't be selected
+    Header(&'a str),
+    Item(&'a PathItem),
+}
+
+/// `width` is the number of columns inside the block, names that don't fit are
+/// cut short around their matches. `bottom_up` draws the first row at the
+/// bottom
 pub fn get_list<'a>(
-    items: &'a BinaryHeap<PathItem>,
-    rows: u16,
+    rows: &'a [Row<'a>],
+    height: u16,
+    width: u16,
     curr_row: Option<usize>,
+    block: Block<'a>,
+    bottom_up: bool,
     colors: &'a Colors,
 ) -> List<'a> {
-    let iter = items.iter().enumerate().map(move |(i, item)| {
+    let iter = rows.iter().enumerate().map(move |(i, row)| {
         let curr_row = curr_row.unwrap_or(0);
-        let upper_index = curr_row.saturating_sub(rows as usize);
+        let upper_index = curr_row.saturating_sub(height as usize);
+        let item = match row {
+            Row::Header(source) => {
+                let available = (width as usize).saturating_sub(HIGHLIGHT_SYMBOL.chars().count());
+                return ListItem::new(Span::styled(
+                    truncate_left(source, available),
+                    Style::default()
+                        .fg(colors.inactive)
+                        .add_modifier(Modifier::BOLD),
+                ));
+            }
+            Row::Item(item) => item,
+        };
 
         // only highlight rows that are visible
-        if i >= upper_index && i < curr_row + rows as usize {
+        if i >= upper_index && i < curr_row + height as usize {
             let mut spans = Vec::new();
-            let mut style = Style::default().fg(colors.fg);
+            let mut style = Style::default().fg(item.source.color.unwrap_or(colors.fg));
             if i == curr_row {
                 style.fg = Some(colors.active);
                 style.add_modifier = Modifier::BOLD;
             }
-            let mut curr_pos: usize = 0;
-            let item_len = item.path.len();
-            for ind in &item.indices {
-                spans.push(Span::styled(&item.path[curr_pos..*ind], style));
-                spans.push(Span::styled(
-                    &item.path[*ind..=*ind],
-                    style.fg(colors.selection),
-                ));
-                curr_pos = ind + 1;
-            }
-            if curr_pos < item_len {
-                spans.push(Span::styled(&item.path[curr_pos..item_len], style));
+            let mark = if item.marked { "+ " } else { "" };
+            let running = if item.running { " ●" } else { "" };
+            let available = (width as usize).saturating_sub(
+                HIGHLIGHT_SYMBOL.chars().count() + mark.chars().count() + running.chars().count(),
+            );
+            let (path, indices) = truncate_around_matches(&item.path, &item.indices, available);
+
+            spans.push(Span::styled(mark, Style::default().fg(colors.selection)));
+            for (range, matched) in match_runs(&path, &indices) {
+                let style = if matched {
+                    style.fg(colors.selection)
+                } else {
+                    style
+                };
+                spans.push(Span::styled(path[range].to_string(), style));
             }
+            spans.push(Span::styled(running, Style::default().fg(colors.active)));
             let line = Line::from(spans);
             ListItem::new(line)
         } else {
-            ListItem::new(item.path)
+            ListItem::new(&*item.path)
         }
     });
 
     List::new(iter)
-        .block(
-            Block::default()
-                .title("Results")
-                .style(Style::default().fg(colors.active)),
-        )
-        .highlight_symbol("▪ ")
-        .direction(ListDirection::TopToBottom)
+        .block(block)
+        .highlight_symbol(HIGHLIGHT_SYMBOL)
+        .direction(if bottom_up {
+            ListDirection::BottomToTop
+        } else {
+            ListDirection::TopToBottom
+        })
 }
 
+/// Cuts `text` down to `width` chars, replacing what was cut at either end
+/// with an ellipsis. The window is moved so the matched chars stay visible
+/// where possible, without matches the end is kept. Returns the text along
+/// with `indices` shifted to it
+fn truncate_around_matches(text: &str, indices: &[usize], width: usize) -> (String, Vec<usize>) {
+    let len = text.chars().count();
+    if len <= width {
+        return (text.to_string(), indices.to_vec());
+    }
+    if width == 0 {
+        return (String::new(), Vec::new());
+    }
+
+    let start = match (indices.first(), indices.last()) {
+        (Some(&first), Some(&last)) => {
+            // the smallest start that still shows the last match, one more
+            // char is needed for the ellipsis unless it is the last char
+            let start = (last + 2).saturating_sub(width).min(len - width);
+            if start == 0 || first > start {
+                start
+            } else {
+                // the matches don't fit at once, show them from the first one
+                first.saturating_sub(1)
+            }
+        }
+        _ => len - width,
+    };
+    let end = start + width;
+
+    let cut_start = start > 0;
+    let cut_end = end < len;
+    let visible_start = start + cut_start as usize;
+    let visible_end = end - cut_end as usize;
+
+    let mut truncated = String::new();
+    if cut_start {
+        truncated.push('…');
+    }
+    truncated.extend(
+        text.chars()
+            .skip(visible_start)
+            .take(visible_end - visible_start),
+    );
+    if cut_end {
+        truncated.push('…');
+    }
+    let indices = indices
+        .iter()
+        .filter(|&&i| i >= visible_start && i < visible_end)
+        .map(|&i| i - start)
+        .collect();
+    (truncated, indices)
+}
+
+/// Splits `text` into byte ranges of consecutive chars that are either all
+/// matched or all unmatched, `indices` are sorted char positions as returned by
+/// the matcher so they are mapped to char boundaries here
+fn match_runs(text: &str, indices: &[usize]) -> Vec<(Range<usize>, bool)> {
+    let mut runs: Vec<(Range<usize>, bool)> = Vec::new();
+    let mut indices = indices.iter().peekable();
+    for (char_pos, (byte_pos, c)) in text.char_indices().enumerate() {
+        while indices.next_if(|&&i| i < char_pos).is_some() {}
+        let matched = indices.next_if_eq(&&char_pos).is_some();
+        let end = byte_pos + c.len_utf8();
+        match runs.last_mut() {
+            Some((range, run_matched)) if *run_matched == matched => range.end = end,
+            _ => runs.push((byte_pos..end, matched)),
+        }
+    }
+    runs
+}
+
+/// The mode followed by the depth offset when it isn't 0, e.g. `NORMAL +1`
+pub fn mode_label(mode: Mode, depth_offset: isize) -> String {
+    match depth_offset {
+        0 => mode.label().to_string(),
+        offset => format!("{} {:+}", mode.label(), offset),
+    }
+}
+
+pub fn get_mode(label: String, colors: &Colors) -> Paragraph<'static> {
+    Paragraph::new(Span::styled(label, Style::default().fg(colors.active)))
+}
+
+/// `found N…` while the walk runs, the spinner being visible, and
+/// `curr/total` once it is done, followed by `notes` like `capped`. The rest
+/// of the width shows `selected`
 pub fn get_total_item_no<'a>(
     total_len: usize,
     curr_len: usize,
+    notes: &[&str],
+    selected: Option<&str>,
+    width: u16,
     colors: &Colors,
     spinner: &'a mut Spinner,
 ) -> Paragraph<'a> {
-    let spin = if spinner.visible {
+    // the spinner is shown for as long as the walk runs, until then the
+    // total isn't known
+    let counts = if spinner.visible {
         spinner.tick();
-        spinner.get_curr()
+        format!("found {}… {} ", total_len, spinner.get_curr())
     } else {
-        ""
+        let notes = if notes.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", notes.join(", "))
+        };
+        format!("{}/{}{} ", curr_len, total_len, notes)
     };
-    let text = format!("{}/{} {}", curr_len, total_len, spin);
-    Paragraph::new(text).block(Block::default().fg(colors.selection))
+    let available = (width as usize).saturating_sub(counts.chars().count());
+    let path = selected
+        .map(|path| truncate_left(path, available))
+        .unwrap_or_default();
+
+    let line = Line::from(vec![
+        Span::styled(counts, Style::default().fg(colors.selection)),
+        Span::styled(path, Style::default().fg(colors.inactive)),
+    ]);
+    Paragraph::new(line)
+}
+
+/// Keeps the end of `text` so it fits in `width` chars, marking the cut with
+/// an ellipsis
+fn truncate_left(text: &str, width: usize) -> String {
+    let len = text.chars().count();
+    if len <= width {
+        return text.to_string();
+    }
+    if width == 0 {
+        return String::new();
+    }
+    let tail: String = text.chars().skip(len - (width - 1)).collect();
+    format!("…{}", tail)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::{Matcher, MatcherKind};
+
+    /// The chars `match_runs` marks as matched, in order
+    fn highlighted(text: &str, indices: &[usize]) -> String {
+        match_runs(text, indices)
+            .into_iter()
+            .filter(|(_, matched)| *matched)
+            .map(|(range, _)| &text[range])
+            .collect()
+    }
+
+    #[test]
+    fn lowercase_query_highlights_mixed_case_path() {
+        let path = "ÉtéMyProjects";
+        for kind in [
+            MatcherKind::Skim,
+            MatcherKind::Clangd,
+            MatcherKind::Substring,
+        ] {
+            let (_, indices) = Matcher::new(kind, false)
+                .fuzzy_indices(path, "proj")
+                .unwrap();
+            assert_eq!(highlighted(path, &indices), "Proj");
+        }
+    }
+
+    #[test]
+    fn truncated_path_keeps_highlights_on_the_matched_chars() {
+        let path = "a-long-tail-ahead-of-ÉtéMyProjects-and-a-suffix";
+        for kind in [
+            MatcherKind::Skim,
+            MatcherKind::Clangd,
+            MatcherKind::Substring,
+        ] {
+            let (_, indices) = Matcher::new(kind, false)
+                .fuzzy_indices(path, "proj")
+                .unwrap();
+            let (truncated, indices) = truncate_around_matches(path, &indices, 16);
+            assert_eq!(truncated.chars().count(), 16);
+            assert_eq!(highlighted(&truncated, &indices), "Proj");
+        }
+    }
+
+    #[test]
+    fn multibyte_chars_are_highlighted_whole() {
+        let path = "café-app";
+        for kind in [
+            MatcherKind::Skim,
+            MatcherKind::Clangd,
+            MatcherKind::Substring,
+        ] {
+            let (_, indices) = Matcher::new(kind, false)
+                .fuzzy_indices(path, "é-a")
+                .unwrap();
+            assert_eq!(indices, [3, 4, 5]);
+            assert_eq!(
+                match_runs(path, &indices),
+                [(0..3, false), (3..7, true), (7..9, false)]
+            );
+        }
+        let (_, indices) = Matcher::new(MatcherKind::Skim, false)
+            .fuzzy_indices(path, "féa")
+            .unwrap();
+        assert_eq!(highlighted(path, &indices), "féa");
+    }
 }
@@ -0,0 +1,27 @@
+use std::{path::PathBuf, process::Command};
+
+/// Directories from `zoxide query --list`, best ranked first. Empty if zoxide
+/// isn't installed or the query fails
+pub fn query() -> Vec<(PathBuf, String)> {
+    let Ok(output) = Command::new("zoxide").args(["query", "--list"]).output() else {
+        return Vec::new();
+    };
+    if !output.status.success() {
+        return Vec::new();
+    }
+    parse_list(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// One path per line into `(dir, dir_name)` pairs like the walker sends
+fn parse_list(output: &str) -> Vec<(PathBuf, String)> {
+    output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .filter_map(|line| {
+            let dir = PathBuf::from(line);
+            let dir_name = dir.file_name()?.to_str()?.to_string();
+            Some((dir, dir_name))
+        })
+        .collect()
+}